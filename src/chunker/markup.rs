@@ -0,0 +1,261 @@
+use super::{structured_chunks, ChunkSizer, FileChunk};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Markup {
+    Markdown,
+    ReStructuredText,
+}
+
+//The markup language of a document by its extension
+pub fn markup(path: &str) -> Option<Markup> {
+    let extension = path.rsplit_once('.')?.1.to_lowercase();
+    match extension.as_str() {
+        "md" | "mdx" | "markdown" => Some(Markup::Markdown),
+        "rst" => Some(Markup::ReStructuredText),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Heading {
+    //1 for top-level headings
+    level: usize,
+    title: String,
+    //Byte offsets of the first line of the heading and of the section's content after it
+    start: usize,
+    content_start: usize,
+}
+
+//Chunks of the sections of the document, with the headings of each section and its parents
+//Sections with nothing but their heading are left out, their heading is part of their subsections' headings
+pub fn section_chunks(markup: Markup, content: &str, sizer: &ChunkSizer) -> Vec<FileChunk> {
+    let headings = match markup {
+        Markup::Markdown => markdown_headings(content),
+        Markup::ReStructuredText => rst_headings(content),
+    };
+
+    let preamble_end = headings
+        .first()
+        .map_or(content.len(), |heading| heading.start);
+    let mut chunks = structured_chunks(content, (0, preamble_end), None, sizer);
+
+    let mut parents: Vec<&Heading> = Vec::new();
+    for (index, heading) in headings.iter().enumerate() {
+        let end = headings
+            .get(index + 1)
+            .map_or(content.len(), |next| next.start);
+        parents.retain(|parent| parent.level < heading.level);
+        parents.push(heading);
+        if content[heading.content_start..end].trim().is_empty() {
+            continue;
+        }
+
+        let titles: Vec<String> = parents.iter().map(|parent| parent.title.clone()).collect();
+        let mut section = structured_chunks(content, (heading.start, end), None, sizer);
+        for chunk in &mut section {
+            chunk.headings = titles.clone();
+        }
+        chunks.extend(section);
+    }
+    chunks
+}
+
+//The lines of the content with their byte offsets, without their line breaks
+fn lines(content: &str) -> Vec<(usize, &str)> {
+    let mut offset = 0;
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            let start = offset;
+            offset += line.len();
+            (start, line.trim_end_matches(['\n', '\r']))
+        })
+        .collect()
+}
+
+//ATX headings, e.g. `## Docker`, and setext headings, underlined with `=` or `-`
+//Lines in fenced code blocks and the front matter aren't headings
+fn markdown_headings(content: &str) -> Vec<Heading> {
+    let lines = lines(content);
+    let mut headings = Vec::new();
+    let mut fence: Option<&str> = None;
+    //Whether the previous line can be the text of a setext heading
+    let mut is_paragraph = false;
+
+    let mut index = 0;
+    if lines.first().map(|(_, line)| line.trim_end()) == Some("---") {
+        index = lines
+            .iter()
+            .skip(1)
+            .position(|(_, line)| matches!(line.trim_end(), "---" | "..."))
+            .map_or(0, |position| position + 2);
+    }
+
+    while index < lines.len() {
+        let (offset, line) = lines[index];
+        let content_start = lines.get(index + 1).map_or(content.len(), |next| next.0);
+        index += 1;
+        //Up to 3 spaces of indentation, more make it an indented code block
+        let indentation = line.len() - line.trim_start_matches(' ').len();
+        let trimmed = line.trim();
+
+        if let Some(marker) = fence {
+            if indentation < 4 && trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if indentation < 4 && (trimmed.starts_with("```") || trimmed.starts_with("~~~")) {
+            fence = Some(&trimmed[..3]);
+            is_paragraph = false;
+            continue;
+        }
+
+        let hashes = trimmed.len() - trimmed.trim_start_matches('#').len();
+        let after_hashes = &trimmed[hashes..];
+        if indentation < 4
+            && (1..=6).contains(&hashes)
+            && (after_hashes.is_empty() || after_hashes.starts_with([' ', '\t']))
+        {
+            headings.push(Heading {
+                level: hashes,
+                title: after_hashes.trim_end_matches('#').trim().to_string(),
+                start: offset,
+                content_start,
+            });
+            is_paragraph = false;
+            continue;
+        }
+
+        let is_underline = !trimmed.is_empty()
+            && (trimmed.chars().all(|c| c == '=') || trimmed.chars().all(|c| c == '-'));
+        if is_paragraph && indentation < 4 && is_underline {
+            let (start, text) = lines[index - 2];
+            headings.push(Heading {
+                level: if trimmed.starts_with('=') { 1 } else { 2 },
+                title: text.trim().to_string(),
+                start,
+                content_start,
+            });
+            is_paragraph = false;
+            continue;
+        }
+        is_paragraph = !trimmed.is_empty();
+    }
+    headings
+}
+
+//Titles underlined, and optionally overlined, with a punctuation character
+//Levels are assigned to the heading styles in the order they're first used, as reStructuredText does
+fn rst_headings(content: &str) -> Vec<Heading> {
+    let lines = lines(content);
+    let mut headings = Vec::new();
+    let mut styles: Vec<(char, bool)> = Vec::new();
+
+    for index in 1..lines.len() {
+        let (_, underline) = lines[index];
+        let (text_start, text) = lines[index - 1];
+        let title = text.trim();
+        let Some(character) = adornment(underline) else {
+            continue;
+        };
+        let is_title = !title.is_empty()
+            && !text.starts_with([' ', '\t'])
+            && adornment(text).is_none()
+            && underline.trim_end().chars().count() >= title.chars().count();
+        if !is_title {
+            continue;
+        }
+
+        let overline = index
+            .checked_sub(2)
+            .map(|overline| lines[overline])
+            .filter(|(_, overline)| overline.trim_end() == underline.trim_end());
+        let style = (character, overline.is_some());
+        let level = match styles.iter().position(|known| *known == style) {
+            Some(position) => position + 1,
+            None => {
+                styles.push(style);
+                styles.len()
+            }
+        };
+        headings.push(Heading {
+            level,
+            title: title.to_string(),
+            start: overline.map_or(text_start, |(start, _)| start),
+            content_start: lines.get(index + 1).map_or(content.len(), |next| next.0),
+        });
+    }
+    headings
+}
+
+//The character of a line made of a single repeated punctuation character
+fn adornment(line: &str) -> Option<char> {
+    let line = line.trim_end();
+    let character = line.chars().next()?;
+    let is_adornment =
+        line.len() >= 2 && character.is_ascii_punctuation() && line.chars().all(|c| c == character);
+    is_adornment.then_some(character)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::{chunk_file, tests::sizer};
+
+    fn headings(chunks: &[FileChunk]) -> Vec<String> {
+        chunks
+            .iter()
+            .map(|chunk| chunk.headings.join(" > "))
+            .collect()
+    }
+
+    #[test]
+    fn test_markdown_sections() {
+        let content = "---\ntitle: Guide\n---\nIntro text.\n\n# Installation\n\n## Docker\n\nRun the image.\n\n```bash\n# Not a heading\ndocker run repo-query\n```\n\n### Compose\n\nUse compose.\n\n## From source ##\n\nBuild it.\n\nUsage\n=====\n\nQuery the API.\n";
+        let chunks = chunk_file("docs/guide.mdx", content, &sizer());
+
+        assert_eq!(
+            headings(&chunks),
+            vec![
+                "",
+                "Installation > Docker",
+                "Installation > Docker > Compose",
+                "Installation > From source",
+                "Usage",
+            ]
+        );
+        assert_eq!(chunks[0].content, "--- title: Guide --- Intro text.");
+        assert!(chunks[1].content.starts_with("## Docker Run the image."));
+        assert!(chunks[1].content.contains("# Not a heading"));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (8, 15));
+        assert_eq!(chunks[4].start_line, 25);
+        assert!(chunks.iter().all(|chunk| chunk.symbol.is_none()));
+    }
+
+    #[test]
+    fn test_rst_sections() {
+        let content = "=========\nRepoQuery\n=========\n\nIntro.\n\nInstallation\n============\n\nPip\n---\n\nInstall it.\n\n----\n\nUsage\n=====\n\nQuery it.\n";
+        let chunks = chunk_file("README.rst", content, &sizer());
+
+        assert_eq!(
+            headings(&chunks),
+            vec![
+                "RepoQuery",
+                "RepoQuery > Installation > Pip",
+                "RepoQuery > Usage"
+            ]
+        );
+        assert_eq!(chunks[0].start_line, 1);
+        assert!(chunks[1].content.contains("----"));
+    }
+
+    #[test]
+    fn test_markup() {
+        assert_eq!(markup("README.md"), Some(Markup::Markdown));
+        assert_eq!(markup("docs/CHANGELOG.MD"), Some(Markup::Markdown));
+        assert_eq!(markup("index.rst"), Some(Markup::ReStructuredText));
+        assert_eq!(markup("notes.txt"), None);
+        assert!(chunk_file("README.md", "", &sizer()).is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+mod markup;
+mod sizer;
+mod syntax;
+
+use serde::{Deserialize, Serialize};
+
+pub use sizer::*;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileChunk {
+    pub content: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    //The function, class or other definition that the chunk is part of, e.g. `Repository::fmt`
+    #[serde(default)]
+    pub symbol: Option<String>,
+    //The headings of the document section that the chunk is part of, from the top level down
+    #[serde(default)]
+    pub headings: Vec<String>,
+}
+
+//Splits the file content into chunks small enough to be embedded as a whole
+//Files in a supported language are split at the boundaries of their definitions, documents by their headings
+pub fn chunk_file(path: &str, content: &str, sizer: &ChunkSizer) -> Vec<FileChunk> {
+    if let Some(markup) = markup::markup(path) {
+        return markup::section_chunks(markup, content, sizer);
+    }
+    syntax::grammar(path)
+        .and_then(|grammar| syntax::syntax_chunks(grammar, content, sizer))
+        .unwrap_or_else(|| text_chunks(content, (0, content.len()), None, sizer))
+}
+
+//A single chunk of the `range` of bytes of the content if it fits, text chunks otherwise
+fn structured_chunks(
+    content: &str,
+    range: (usize, usize),
+    symbol: Option<&str>,
+    sizer: &ChunkSizer,
+) -> Vec<FileChunk> {
+    if sizer.fits(&content[range.0..range.1]) {
+        chunk_range(content, range, symbol).into_iter().collect()
+    } else {
+        text_chunks(content, range, symbol, sizer)
+    }
+}
+
+//Splits the `range` of bytes of the content by its paragraphs, lines and words
+//Each chunk starts with the end of the previous one, so that text split mid-sentence keeps its context
+fn text_chunks(
+    content: &str,
+    range: (usize, usize),
+    symbol: Option<&str>,
+    sizer: &ChunkSizer,
+) -> Vec<FileChunk> {
+    let text = &content[range.0..range.1];
+    let mut chunks = Vec::new();
+    let mut previous: Option<(usize, &str)> = None;
+    for (offset, chunk) in sizer.split(text) {
+        let overlap_start = previous.and_then(|(previous_offset, previous)| {
+            sizer
+                .overlap_start(previous)
+                .map(|start| previous_offset + start)
+        });
+        let start = range.0 + overlap_start.unwrap_or(offset);
+        let end = range.0 + offset + chunk.len();
+        chunks.extend(chunk_range(content, (start, end), symbol));
+        previous = Some((offset, chunk));
+    }
+    chunks
+}
+
+//The chunk of the `range` of bytes of the content, unless it's blank
+fn chunk_range(content: &str, range: (usize, usize), symbol: Option<&str>) -> Option<FileChunk> {
+    let chunk = &content[range.0..range.1];
+    let (start_line, end_line) = line_range(content, range.0, chunk);
+    let chunk = FileChunk {
+        content: clean_chunk(chunk),
+        start_line,
+        end_line,
+        symbol: symbol.map(String::from),
+        headings: Vec::new(),
+    };
+    (!chunk.content.is_empty()).then_some(chunk)
+}
+
+//Remove extra whitespaces from a chunk
+fn clean_chunk(chunk: &str) -> String {
+    chunk.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+//1-based line numbers of the first and last line of a chunk at `offset` in `content`
+fn line_range(content: &str, offset: usize, chunk: &str) -> (usize, usize) {
+    let start_line = content[..offset].matches('\n').count() + 1;
+    let end_line = start_line + chunk.trim_end().matches('\n').count();
+    (start_line, end_line)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashMap;
+
+    use tokenizers::{models::wordlevel::WordLevel, pre_tokenizers::whitespace::Whitespace};
+
+    use super::*;
+
+    //The chunk sizes in characters that the tests are written for
+    pub fn sizer() -> ChunkSizer {
+        ChunkSizer::characters(300..=400, 1000, 0)
+    }
+
+    //Every word and punctuation is a single token
+    fn word_tokenizer() -> tokenizers::Tokenizer {
+        let model = WordLevel::builder()
+            .vocab(HashMap::from([("[UNK]".to_string(), 0)]))
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = tokenizers::Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Whitespace {});
+        tokenizer
+    }
+
+    #[test]
+    fn test_line_range() {
+        let content = "fn main() {\n    println!();\n}\n\nfn other() {}\n";
+        let offset = content.find("fn other").unwrap();
+        assert_eq!(line_range(content, offset, "fn other() {}"), (5, 5));
+        assert_eq!(
+            line_range(content, 0, "fn main() {\n    println!();\n}\n"),
+            (1, 3)
+        );
+    }
+
+    #[test]
+    fn test_chunk_file() {
+        let paragraph = "word ".repeat(60);
+        let content = format!("{paragraph}\n\n{paragraph}\n\n{paragraph}\n");
+        let chunks = chunk_file("notes.txt", &content, &sizer());
+
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks[0].start_line, 1);
+        assert_eq!(chunks.last().unwrap().end_line, 5);
+        assert!(chunks.iter().all(|chunk| !chunk.content.contains("  ")));
+
+        assert!(chunks.iter().all(|chunk| chunk.symbol.is_none()));
+
+        assert!(chunk_file("notes.txt", "", &sizer()).is_empty());
+        assert!(chunk_file("src/main.rs", "", &sizer()).is_empty());
+    }
+
+    #[test]
+    fn test_token_chunks() {
+        let sizer = ChunkSizer::tokens(word_tokenizer(), 40, 8);
+        let sentence = |index: usize| format!("Sentence {index} has six tokens.");
+        let content = (0..30).map(sentence).collect::<Vec<_>>().join("\n");
+        let chunks = chunk_file("notes.txt", &content, &sizer);
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| sizer.size(&chunk.content) <= 40));
+        //Each chunk starts with the end of the previous one
+        for pair in chunks.windows(2) {
+            let (previous, next) = (&pair[0], &pair[1]);
+            assert!(next.start_line <= previous.end_line);
+            let overlap = next.content.split_inclusive('.').next().unwrap().trim();
+            assert!(previous.content.ends_with(overlap));
+            assert!(sizer.size(overlap) <= 8);
+        }
+        assert_eq!(chunks[0].start_line, 1);
+        assert_eq!(chunks.last().unwrap().end_line, 30);
+
+        //Definitions that don't fit are split
+        let function = format!("fn main() {{\n{}}}\n", "    run();\n".repeat(20));
+        let chunks = chunk_file("src/main.rs", &function, &sizer);
+        assert!(chunks.len() > 1);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.symbol.as_deref() == Some("main")));
+    }
+}
@@ -0,0 +1,106 @@
+use std::ops::RangeInclusive;
+
+use text_splitter::TextSplitter;
+use tokenizers::Tokenizer;
+
+use crate::constants::FILE_CHUNKER_CAPACITY_RANGE;
+
+//Measures chunks in the tokens of the embedding model, so that every chunk fits in its max sequence length
+pub struct ChunkSizer {
+    //Chunks are measured in characters without a tokenizer, in tests
+    tokenizer: Option<Tokenizer>,
+    //Size of the chunks that text is split into
+    text_chunk_size: RangeInclusive<usize>,
+    //Size of the largest chunk, e.g. a whole function
+    max_size: usize,
+    //Size of the end of a text chunk that's repeated at the start of the next one
+    overlap: usize,
+}
+
+impl ChunkSizer {
+    //Text chunks leave room for the overlap within `max_tokens`
+    pub fn tokens(tokenizer: Tokenizer, max_tokens: usize, overlap_tokens: usize) -> Self {
+        let overlap_tokens = overlap_tokens.min(max_tokens / 2);
+        let text_chunk_end = (*FILE_CHUNKER_CAPACITY_RANGE.end()).min(max_tokens - overlap_tokens);
+        let text_chunk_start = (*FILE_CHUNKER_CAPACITY_RANGE.start()).min(text_chunk_end);
+        Self {
+            tokenizer: Some(tokenizer),
+            text_chunk_size: text_chunk_start..=text_chunk_end,
+            max_size: max_tokens,
+            overlap: overlap_tokens,
+        }
+    }
+
+    //Measures characters, for tests that don't load a model's tokenizer
+    #[cfg(test)]
+    pub fn characters(
+        text_chunk_size: RangeInclusive<usize>,
+        max_chars: usize,
+        overlap_chars: usize,
+    ) -> Self {
+        Self {
+            tokenizer: None,
+            text_chunk_size,
+            max_size: max_chars,
+            overlap: overlap_chars,
+        }
+    }
+
+    pub fn size(&self, text: &str) -> usize {
+        match &self.tokenizer {
+            Some(tokenizer) => tokenizer
+                .encode(text, false)
+                .map(|encoding| encoding.len())
+                //Tokenizers only fail on text they can't represent, which the model can't embed either
+                .unwrap_or(usize::MAX),
+            None => text.chars().count(),
+        }
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn overlap(&self) -> usize {
+        self.overlap
+    }
+
+    pub fn fits(&self, text: &str) -> bool {
+        self.size(text) <= self.max_size
+    }
+
+    //Byte offsets and text of the chunks that the text is split into by its paragraphs, lines and words
+    pub fn split<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)> {
+        let capacity = self.text_chunk_size.clone();
+        match &self.tokenizer {
+            Some(tokenizer) => TextSplitter::new(tokenizer)
+                .with_trim_chunks(true)
+                .chunk_indices(text, capacity)
+                .collect(),
+            None => TextSplitter::default()
+                .with_trim_chunks(true)
+                .chunk_indices(text, capacity)
+                .collect(),
+        }
+    }
+
+    //Byte offset of the overlap at the end of the text, starting at a word
+    pub fn overlap_start(&self, text: &str) -> Option<usize> {
+        let word_starts = text
+            .char_indices()
+            .rev()
+            .filter(|(index, c)| {
+                !c.is_whitespace() && text[..*index].ends_with(char::is_whitespace)
+            })
+            .map(|(index, _)| index);
+
+        let mut start = None;
+        for word_start in word_starts {
+            if self.size(&text[word_start..]) > self.overlap {
+                break;
+            }
+            start = Some(word_start);
+        }
+        start
+    }
+}
@@ -0,0 +1,403 @@
+use tree_sitter::{Language, Node, Parser};
+
+use super::{structured_chunks, ChunkSizer, FileChunk};
+
+//How the syntax tree of a language is split into chunks
+pub struct Grammar {
+    language: fn() -> Language,
+    //Kinds of the nodes that define a named symbol, e.g. functions and classes
+    definitions: &'static [&'static str],
+    //Definitions that are split into their members if they're too large
+    containers: &'static [&'static str],
+    //Kinds of the nodes that document the definition after them
+    comments: &'static [&'static str],
+    //Separates the names of nested symbols, e.g. `Repository::fmt`
+    separator: &'static str,
+}
+
+const RUST: Grammar = Grammar {
+    language: tree_sitter_rust::language,
+    definitions: &[
+        "function_item",
+        "impl_item",
+        "trait_item",
+        "struct_item",
+        "enum_item",
+        "union_item",
+        "mod_item",
+        "macro_definition",
+    ],
+    containers: &["impl_item", "trait_item", "mod_item"],
+    comments: &["line_comment", "block_comment", "attribute_item"],
+    separator: "::",
+};
+
+const JAVASCRIPT_DEFINITIONS: &[&str] = &[
+    "function_declaration",
+    "generator_function_declaration",
+    "class_declaration",
+    "abstract_class_declaration",
+    "method_definition",
+    "interface_declaration",
+    "enum_declaration",
+    "type_alias_declaration",
+    "lexical_declaration",
+    "variable_declaration",
+    "export_statement",
+];
+
+const JAVASCRIPT_CONTAINERS: &[&str] = &["class_declaration", "abstract_class_declaration"];
+
+const TYPESCRIPT: Grammar = Grammar {
+    language: tree_sitter_typescript::language_typescript,
+    definitions: JAVASCRIPT_DEFINITIONS,
+    containers: JAVASCRIPT_CONTAINERS,
+    comments: &["comment"],
+    separator: ".",
+};
+
+const TSX: Grammar = Grammar {
+    language: tree_sitter_typescript::language_tsx,
+    ..TYPESCRIPT
+};
+
+const JAVASCRIPT: Grammar = Grammar {
+    language: tree_sitter_javascript::language,
+    ..TYPESCRIPT
+};
+
+const PYTHON: Grammar = Grammar {
+    language: tree_sitter_python::language,
+    definitions: &[
+        "function_definition",
+        "class_definition",
+        "decorated_definition",
+    ],
+    containers: &["class_definition"],
+    comments: &["comment"],
+    separator: ".",
+};
+
+const GO: Grammar = Grammar {
+    language: tree_sitter_go::language,
+    definitions: &[
+        "function_declaration",
+        "method_declaration",
+        "type_declaration",
+    ],
+    containers: &[],
+    comments: &["comment"],
+    separator: ".",
+};
+
+const JAVA: Grammar = Grammar {
+    language: tree_sitter_java::language,
+    definitions: &[
+        "class_declaration",
+        "interface_declaration",
+        "enum_declaration",
+        "record_declaration",
+        "method_declaration",
+        "constructor_declaration",
+    ],
+    containers: &[
+        "class_declaration",
+        "interface_declaration",
+        "record_declaration",
+    ],
+    comments: &["line_comment", "block_comment"],
+    separator: ".",
+};
+
+//The grammar of a file by its extension
+pub fn grammar(path: &str) -> Option<&'static Grammar> {
+    let extension = path.rsplit_once('.')?.1;
+    match extension {
+        "rs" => Some(&RUST),
+        "ts" | "mts" | "cts" => Some(&TYPESCRIPT),
+        "tsx" => Some(&TSX),
+        "js" | "mjs" | "cjs" | "jsx" => Some(&JAVASCRIPT),
+        "py" | "pyi" => Some(&PYTHON),
+        "go" => Some(&GO),
+        "java" => Some(&JAVA),
+        _ => None,
+    }
+}
+
+//Chunks aligned to the definitions of the file, named after the symbol they define
+//None if the file can't be parsed
+pub fn syntax_chunks(
+    grammar: &Grammar,
+    content: &str,
+    sizer: &ChunkSizer,
+) -> Option<Vec<FileChunk>> {
+    let mut parser = Parser::new();
+    parser.set_language((grammar.language)()).ok()?;
+    let tree = parser.parse(content, None)?;
+    let root = tree.root_node();
+    if root.has_error() {
+        return None;
+    }
+
+    let mut chunker = SyntaxChunker {
+        grammar,
+        content,
+        sizer,
+        chunks: Vec::new(),
+    };
+    chunker.chunk_children(root, None, None);
+    Some(chunker.chunks)
+}
+
+struct SyntaxChunker<'a> {
+    grammar: &'a Grammar,
+    content: &'a str,
+    sizer: &'a ChunkSizer,
+    chunks: Vec<FileChunk>,
+}
+
+impl SyntaxChunker<'_> {
+    //Consecutive nodes other than definitions are grouped into chunks that fit the chunk size
+    //Comments right before a definition are part of its chunk
+    fn chunk_children(&mut self, node: Node, scope: Option<&str>, header: Option<(usize, usize)>) {
+        let mut pending: Option<(usize, usize)> = header;
+        let mut comments: Option<(usize, usize)> = None;
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            let range = (child.start_byte(), child.end_byte());
+            if self.grammar.comments.contains(&child.kind()) {
+                comments = Some(extend(comments, range));
+                continue;
+            }
+
+            match self.symbol_name(child) {
+                Some(name) => {
+                    self.flush(pending.take(), scope);
+                    let start = comments.take().map_or(range.0, |comments| comments.0);
+                    let symbol = match scope {
+                        Some(scope) => format!("{scope}{}{name}", self.grammar.separator),
+                        None => name,
+                    };
+                    self.chunk_definition(child, (start, range.1), symbol);
+                }
+                None => {
+                    if let Some(comments) = comments.take() {
+                        pending = self.push(pending, comments, scope);
+                    }
+                    pending = self.push(pending, range, scope);
+                }
+            }
+        }
+        if let Some(comments) = comments {
+            pending = self.push(pending, comments, scope);
+        }
+        self.flush(pending, scope);
+    }
+
+    //Adds a range to the pending group, flushing the group first if it would get too large
+    fn push(
+        &mut self,
+        pending: Option<(usize, usize)>,
+        range: (usize, usize),
+        scope: Option<&str>,
+    ) -> Option<(usize, usize)> {
+        match pending {
+            Some(pending) if !self.sizer.fits(&self.content[pending.0..range.1]) => {
+                self.flush(Some(pending), scope);
+                Some(range)
+            }
+            pending => Some(extend(pending, range)),
+        }
+    }
+
+    fn flush(&mut self, range: Option<(usize, usize)>, symbol: Option<&str>) {
+        if let Some(range) = range {
+            self.chunk_range(range, symbol);
+        }
+    }
+
+    //Large containers are split into their members, other large definitions into text chunks
+    fn chunk_definition(&mut self, node: Node, range: (usize, usize), symbol: String) {
+        let definition = inner_definition(node);
+        let is_large = !self.sizer.fits(&self.content[range.0..range.1]);
+        match definition.child_by_field_name("body") {
+            Some(body) if is_large && self.grammar.containers.contains(&definition.kind()) => {
+                let header = (range.0, body.start_byte());
+                self.chunk_children(body, Some(&symbol), Some(header));
+            }
+            _ => self.chunk_range(range, Some(&symbol)),
+        }
+    }
+
+    fn chunk_range(&mut self, range: (usize, usize), symbol: Option<&str>) {
+        self.chunks
+            .extend(structured_chunks(self.content, range, symbol, self.sizer));
+    }
+
+    //The name of the symbol that a node defines, if it's a definition
+    fn symbol_name(&self, node: Node) -> Option<String> {
+        if !self.grammar.definitions.contains(&node.kind()) {
+            return None;
+        }
+        let text = |node: Node| node.utf8_text(self.content.as_bytes()).ok();
+        let definition = inner_definition(node);
+
+        match definition.kind() {
+            //`impl Display for Repository` defines the methods of `Repository`
+            "impl_item" => text(definition.child_by_field_name("type")?).map(String::from),
+            //Variables are definitions when they're assigned a function or a class
+            "lexical_declaration" | "variable_declaration" => {
+                let declarator = definition.named_child(0)?;
+                let value = declarator.child_by_field_name("value")?;
+                let is_definition = matches!(
+                    value.kind(),
+                    "arrow_function" | "function" | "function_expression" | "class"
+                );
+                if !is_definition {
+                    return None;
+                }
+                text(declarator.child_by_field_name("name")?).map(String::from)
+            }
+            //Methods are named after their receiver type, e.g. `Server.Start`
+            "method_declaration" if definition.child_by_field_name("receiver").is_some() => {
+                let receiver = definition.child_by_field_name("receiver")?;
+                let name = text(definition.child_by_field_name("name")?)?;
+                match find_kind(receiver, "type_identifier").and_then(text) {
+                    Some(receiver) => Some(format!("{receiver}.{name}")),
+                    None => Some(name.to_string()),
+                }
+            }
+            "type_declaration" => {
+                let spec = find_kind(definition, "type_spec")?;
+                text(spec.child_by_field_name("name")?).map(String::from)
+            }
+            //Definitions without a name, e.g. `export default {}`, are chunked as other code
+            _ => text(definition.child_by_field_name("name")?).map(String::from),
+        }
+    }
+}
+
+//The definition that decorators or an `export` apply to
+fn inner_definition(node: Node) -> Node {
+    let inner = match node.kind() {
+        "decorated_definition" => node.child_by_field_name("definition"),
+        "export_statement" => node.child_by_field_name("declaration"),
+        _ => None,
+    };
+    inner.map_or(node, inner_definition)
+}
+
+fn find_kind<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+    if node.kind() == kind {
+        return Some(node);
+    }
+    let mut cursor = node.walk();
+    let children: Vec<Node<'a>> = node.named_children(&mut cursor).collect();
+    children
+        .into_iter()
+        .find_map(|child| find_kind(child, kind))
+}
+
+fn extend(range: Option<(usize, usize)>, other: (usize, usize)) -> (usize, usize) {
+    match range {
+        Some(range) => (range.0.min(other.0), range.1.max(other.1)),
+        None => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::{chunk_file, tests::sizer};
+
+    fn symbols(chunks: &[FileChunk]) -> Vec<Option<&str>> {
+        chunks.iter().map(|chunk| chunk.symbol.as_deref()).collect()
+    }
+
+    //A function body longer than a single chunk
+    fn long_body(statement: &str) -> String {
+        format!("    {statement}\n").repeat(1000 / statement.len())
+    }
+
+    #[test]
+    fn test_rust_chunks() {
+        let content = format!(
+            "use std::fmt;\n\n//The entry point\n#[inline]\nfn main() {{\n    run();\n}}\n\nstruct Server;\n\nimpl Server {{\n    fn start(&self) {{\n{}    }}\n\n    fn stop(&self) {{}}\n}}\n",
+            long_body("self.listen();")
+        );
+        let chunks = chunk_file("src/main.rs", &content, &sizer());
+
+        assert_eq!(chunks[0].symbol, None);
+        assert_eq!(chunks[0].content, "use std::fmt;");
+        //Comments and attributes are part of the definition after them
+        assert_eq!(chunks[1].symbol.as_deref(), Some("main"));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (3, 7));
+        assert!(chunks[1]
+            .content
+            .starts_with("//The entry point #[inline] fn main()"));
+        assert_eq!(chunks[2].symbol.as_deref(), Some("Server"));
+
+        //The large impl is split into its methods, the large method into text chunks
+        assert_eq!(chunks[3].symbol.as_deref(), Some("Server"));
+        assert_eq!(chunks[3].content, "impl Server");
+        let methods = &chunks[4..];
+        assert!(methods.len() > 2);
+        assert!(methods[..methods.len() - 1]
+            .iter()
+            .all(|chunk| chunk.symbol.as_deref() == Some("Server::start")));
+        assert_eq!(methods[0].start_line, 12);
+        assert_eq!(
+            methods.last().unwrap().symbol.as_deref(),
+            Some("Server::stop")
+        );
+    }
+
+    #[test]
+    fn test_language_symbols() {
+        let python = "import os\n\nclass Client:\n    @property\n    def url(self):\n        return os.environ['URL']\n";
+        assert_eq!(
+            symbols(&chunk_file("client.py", python, &sizer())),
+            vec![None, Some("Client")]
+        );
+
+        let typescript = "export const fetchRepo = async (name: string) => {\n  return name;\n};\n\nconst limit = 3;\n\nexport interface Repo {\n  name: string;\n}\n";
+        assert_eq!(
+            symbols(&chunk_file("src/repo.ts", typescript, &sizer())),
+            vec![Some("fetchRepo"), None, Some("Repo")]
+        );
+
+        let go = "package server\n\ntype Server struct{}\n\n// Start starts the server\nfunc (s *Server) Start() error {\n\treturn nil\n}\n";
+        let chunks = chunk_file("server.go", go, &sizer());
+        assert_eq!(
+            symbols(&chunks),
+            vec![None, Some("Server"), Some("Server.Start")]
+        );
+        assert_eq!(chunks[2].start_line, 5);
+
+        let java = format!(
+            "package app;\n\npublic class App {{\n    private int port;\n\n    public App() {{}}\n\n    public void start() {{\n{}    }}\n}}\n",
+            long_body("listen(port);")
+        );
+        let chunks = chunk_file("App.java", &java, &sizer());
+        assert_eq!(chunks[0].symbol, None);
+        assert!(chunks[1..].iter().all(|chunk| chunk
+            .symbol
+            .as_deref()
+            .unwrap()
+            .starts_with("App")));
+        assert!(symbols(&chunks).contains(&Some("App.App")));
+        assert!(symbols(&chunks).contains(&Some("App.start")));
+    }
+
+    #[test]
+    fn test_syntax_chunks_fallback() {
+        //Files that don't parse are split as text
+        let chunks = chunk_file("src/broken.rs", "fn main( {\n", &sizer());
+        assert_eq!(symbols(&chunks), vec![None]);
+
+        assert!(grammar("README.md").is_none());
+        assert!(grammar("Makefile").is_none());
+        assert!(grammar("src/app.tsx").is_some());
+    }
+}
@@ -3,20 +3,93 @@ use std::ops::RangeInclusive;
 // Env var defaults
 pub const WEBSERVER_PORT_DEFAULT: &str = "3000";
 pub const QDRANT_URL_DEFAULT: &str = "http://localhost:6334";
+pub const EMBEDDED_DB_PATH_DEFAULT: &str = "./data";
+pub const CONTENT_STORE_PATH_DEFAULT: &str = "./data/contents";
+pub const OPENAI_API_BASE_DEFAULT: &str = "https://api.openai.com/v1";
+pub const GITLAB_URL_DEFAULT: &str = "https://gitlab.com";
+pub const GITEA_URL_DEFAULT: &str = "https://gitea.com";
+pub const MAX_FILE_BYTES_DEFAULT: u64 = 512 * 1024;
+pub const CHUNK_MAX_TOKENS_DEFAULT: usize = 256;
+pub const CHUNK_OVERLAP_TOKENS_DEFAULT: usize = 32;
+
+//Qdrant
+//Not a valid GitHub owner name, so it can't clash with a repository collection
+pub const REPOSITORIES_COLLECTION_NAME: &str = "__repositories";
+
+//Embedded DB
+//Collections with fewer chunks are searched exhaustively
+pub const EMBEDDED_INDEX_MIN_CHUNKS: usize = 1000;
+pub const EMBEDDED_INDEX_ITERATIONS: usize = 10;
+//Number of clusters searched for a query
+pub const EMBEDDED_INDEX_PROBES: usize = 8;
+//Header of the collection files, the format version is bumped when the stored types change
+pub const EMBEDDED_DB_FILE_MAGIC: [u8; 8] = *b"RQEMBDB\0";
+pub const EMBEDDED_DB_FORMAT_VERSION: u32 = 2;
 
 //Embeddings
 pub const EMBEDDINGS_DIMENSION: usize = 384;
+//Max sequence length that all-MiniLM-L6-v2 was trained with, longer texts embed worse
+//fastembed's `max_length` is larger, so the model's limit is what chunks are capped to
+pub const EMBEDDINGS_MAX_SEQUENCE_LENGTH: usize = 256;
 
 //Actix-web
 pub const SSE_CHANNEL_BUFFER_SIZE: usize = 1;
 pub const HOME_ROUTE_REDIRECT_URL: &str = "https://opensauced.pizza";
+//Header of the optional per-request GitHub token
+pub const GITHUB_TOKEN_HEADER: &str = "X-GitHub-Token";
+//Header of the operators' token, required to delete collections
+pub const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
 
 //OpenAI
 pub const CHAT_COMPLETION_TEMPERATURE: f64 = 0.7;
 pub const CHAT_COMPLETION_MODEL: &str = "gpt-3.5-turbo";
+//Covers streamed responses until their last delta
+pub const CHAT_COMPLETION_TIMEOUT_SECS: u64 = 120;
 
 //Semantic search
 pub const MAX_FILES_COUNT: usize = 1000;
-pub const FILE_CHUNKER_CAPACITY_RANGE: RangeInclusive<usize> = 300..=400;
+//Tokens of the chunks that text is split into, definitions and document sections up to `CHUNK_MAX_TOKENS` are a single chunk
+pub const FILE_CHUNKER_CAPACITY_RANGE: RangeInclusive<usize> = 96..=128;
+//Version of the chunking and of the indexes built from the chunks
+//Bump it when they change, so that collections indexed by an earlier version are re-indexed in full
+pub const INDEX_VERSION: u32 = 1;
 pub const RELEVANT_FILES_LIMIT: usize = 3;
 pub const RELEVANT_CHUNKS_LIMIT: usize = 2;
+//Files ranked by each of the vector and lexical searches, before their rankings are fused
+pub const HYBRID_SEARCH_CANDIDATE_FILES: usize = 10;
+//Dampens the weight of the top ranks in reciprocal rank fusion
+pub const RECIPROCAL_RANK_FUSION_K: f32 = 60.0;
+//BM25 term frequency saturation and chunk length normalization
+pub const BM25_K1: f32 = 1.2;
+pub const BM25_B: f32 = 0.75;
+//Assumed average number of terms of a chunk, for the BM25 weights stored in Qdrant
+pub const BM25_AVERAGE_CHUNK_TERMS: f32 = 80.0;
+//Words too common in questions to tell chunks apart
+pub const LEXICAL_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "can", "do", "does", "for", "from", "how",
+    "in", "is", "it", "its", "of", "on", "or", "that", "the", "this", "to", "what", "when",
+    "where", "which", "who", "why", "with",
+];
+
+//File contents
+//Number of leading bytes checked for NUL bytes, as git does to detect binary files
+pub const SNIFF_BYTES: usize = 8000;
+//Files with a longer average line length are considered minified
+pub const MINIFIED_LINE_LENGTH: usize = 500;
+//Markers in the header comments of generated files
+pub const GENERATED_MARKERS: &[&str] = &["@generated", "DO NOT EDIT", "<auto-generated"];
+//Number of skipped files listed in the `EMBED_REPO` event
+pub const SKIPPED_FILES_REPORT_LIMIT: usize = 100;
+
+//Token-scoped collections
+pub const ACCESS_CACHE_TTL_SECS: u64 = 5 * 60;
+
+//Embed jobs
+pub const EMBED_JOBS_CONCURRENCY: usize = 2;
+pub const EMBED_JOB_TTL_SECS: u64 = 60 * 60;
+
+//Conversations
+pub const CONVERSATION_TTL_SECS: u64 = 30 * 60;
+pub const CONVERSATION_STORE_CAPACITY: usize = 1000;
+pub const CONVERSATION_HISTORY_LIMIT: usize = 50;
+pub const CONVERSATION_CHUNKS_LIMIT: usize = 20;
@@ -0,0 +1,238 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{
+    constants::CONTENT_STORE_PATH_DEFAULT,
+    github::{File, Repository},
+    prelude::*,
+};
+
+//Stores the files of every indexed repository as they were at the indexed commit
+//Snapshots are kept at `{CONTENT_STORE_PATH}/{repository key}/{commit SHA}/{file path}`
+//Its methods block on file I/O, async callers run them with `spawn_blocking`
+#[derive(Clone)]
+pub struct ContentStore {
+    path: PathBuf,
+}
+
+//The files of a repository at a commit
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    path: PathBuf,
+}
+
+impl ContentStore {
+    pub fn initialize() -> Result<ContentStore> {
+        let path = match std::env::var("CONTENT_STORE_PATH") {
+            Ok(path) if !path.is_empty() => path,
+            _ => CONTENT_STORE_PATH_DEFAULT.to_string(),
+        };
+        Self::open(PathBuf::from(path))
+    }
+
+    pub fn open(path: PathBuf) -> Result<ContentStore> {
+        fs::create_dir_all(&path)?;
+        Ok(ContentStore { path })
+    }
+
+    //Writes to a temporary directory first, so that a crash can't leave a partial snapshot
+    //An existing snapshot of the commit is kept if it has the same files, and replaced if it was saved with other rules
+    pub fn save(&self, repository: &Repository, commit_sha: &str, files: &[File]) -> Result<()> {
+        let snapshot_path = self.snapshot_path(repository, commit_sha)?;
+        if snapshot_path.is_dir() {
+            let paths: HashSet<PathBuf> = files
+                .iter()
+                .filter_map(|file| relative_path(&file.path))
+                .map(Path::to_path_buf)
+                .collect();
+            if stored_paths(&snapshot_path)? == paths {
+                return Ok(());
+            }
+        }
+        let temp_path = snapshot_path.with_extension("tmp");
+        if temp_path.exists() {
+            fs::remove_dir_all(&temp_path)?;
+        }
+
+        for file in files {
+            let Some(file_path) = relative_path(&file.path) else {
+                continue;
+            };
+            let file_path = temp_path.join(file_path);
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(file_path, &file.content)?;
+        }
+        fs::create_dir_all(&temp_path)?;
+        if snapshot_path.is_dir() {
+            let old_path = snapshot_path.with_extension("old");
+            if old_path.exists() {
+                fs::remove_dir_all(&old_path)?;
+            }
+            fs::rename(&snapshot_path, &old_path)?;
+            fs::rename(temp_path, snapshot_path)?;
+            fs::remove_dir_all(old_path)?;
+        } else {
+            fs::rename(temp_path, snapshot_path)?;
+        }
+        Ok(())
+    }
+
+    pub fn snapshot(&self, repository: &Repository, commit_sha: &str) -> Option<Snapshot> {
+        let path = self.snapshot_path(repository, commit_sha).ok()?;
+        path.is_dir().then_some(Snapshot { path })
+    }
+
+    //Removes the snapshots of the repository other than the one of `commit_sha`
+    pub fn prune(&self, repository: &Repository, commit_sha: &str) -> Result<()> {
+        let snapshot_path = self.snapshot_path(repository, commit_sha)?;
+        let Ok(entries) = fs::read_dir(self.repository_path(repository)) else {
+            return Ok(());
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path != snapshot_path {
+                fs::remove_dir_all(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, repository: &Repository) -> Result<()> {
+        let path = self.repository_path(repository);
+        if path.exists() {
+            fs::remove_dir_all(path)?;
+        }
+        Ok(())
+    }
+
+    //Repository IDs may contain characters that aren't valid in file names, e.g. `/` in branch names
+    fn repository_path(&self, repository: &Repository) -> PathBuf {
+        let repo_id = repository.to_string();
+        let directory_name = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, repo_id.as_bytes());
+        self.path.join(directory_name.to_string())
+    }
+
+    fn snapshot_path(&self, repository: &Repository, commit_sha: &str) -> Result<PathBuf> {
+        let is_commit_sha =
+            !commit_sha.is_empty() && commit_sha.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_commit_sha {
+            return Err(anyhow::anyhow!("Invalid commit SHA {commit_sha}"));
+        }
+        Ok(self.repository_path(repository).join(commit_sha))
+    }
+}
+
+impl Snapshot {
+    pub fn read_file(&self, path: &str) -> Option<String> {
+        fs::read_to_string(self.path.join(relative_path(path)?)).ok()
+    }
+}
+
+//Paths of the files in a snapshot, relative to its directory
+fn stored_paths(snapshot_path: &Path) -> Result<HashSet<PathBuf>> {
+    let mut paths = HashSet::new();
+    let mut directories = vec![snapshot_path.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if let Ok(path) = path.strip_prefix(snapshot_path) {
+                paths.insert(path.to_path_buf());
+            }
+        }
+    }
+    Ok(paths)
+}
+
+//Paths outside of the snapshot's directory are rejected
+fn relative_path(path: &str) -> Option<&Path> {
+    let path = Path::new(path);
+    let is_relative = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    (is_relative && !path.as_os_str().is_empty()).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Provider;
+
+    fn repository() -> Repository {
+        Repository {
+            owner: "open-sauced".to_string(),
+            name: "ai".to_string(),
+            branch: "feature/contents".to_string(),
+            provider: Provider::GitHub,
+        }
+    }
+
+    fn file(path: &str, content: &str) -> File {
+        File {
+            path: path.to_string(),
+            content: content.to_string(),
+            length: content.len(),
+        }
+    }
+
+    #[test]
+    fn test_content_store() {
+        let path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let store = ContentStore::open(path.clone()).unwrap();
+        let (first, second) = ("a1b2c3", "d4e5f6");
+
+        store
+            .save(
+                &repository(),
+                first,
+                &[
+                    file("README.md", "# AI\n"),
+                    file("src/main.rs", "fn main() {}\n"),
+                    file("../outside.rs", ""),
+                ],
+            )
+            .unwrap();
+        store
+            .save(&repository(), second, &[file("README.md", "# Changed\n")])
+            .unwrap();
+        assert!(store.save(&repository(), "../first", &[]).is_err());
+        assert!(!path.join("outside.rs").exists());
+
+        let snapshot = store.snapshot(&repository(), first).unwrap();
+        assert_eq!(snapshot.read_file("README.md").unwrap(), "# AI\n");
+        assert_eq!(snapshot.read_file("src/main.rs").unwrap(), "fn main() {}\n");
+        assert!(snapshot.read_file("../../contents").is_none());
+        assert!(snapshot.read_file("missing.rs").is_none());
+
+        let snapshot = store.snapshot(&repository(), second).unwrap();
+        assert_eq!(snapshot.read_file("README.md").unwrap(), "# Changed\n");
+
+        //Saving the commit with other rules replaces its files
+        store
+            .save(
+                &repository(),
+                second,
+                &[file("src/lib.rs", "pub fn lib() {}\n")],
+            )
+            .unwrap();
+        let snapshot = store.snapshot(&repository(), second).unwrap();
+        assert!(snapshot.read_file("README.md").is_none());
+        assert_eq!(
+            snapshot.read_file("src/lib.rs").unwrap(),
+            "pub fn lib() {}\n"
+        );
+
+        store.prune(&repository(), second).unwrap();
+        assert!(store.snapshot(&repository(), first).is_none());
+        assert!(store.snapshot(&repository(), second).is_some());
+
+        store.delete(&repository()).unwrap();
+        assert!(store.snapshot(&repository(), second).is_none());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
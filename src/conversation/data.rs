@@ -1,49 +1,197 @@
 use crate::prelude::*;
-use crate::{github::Repository, utils::functions::Function};
+use crate::{
+    github::{ChunkEmbeddings, Repository},
+    utils::functions::Function,
+};
 use openai_api_rs::v1::chat_completion::FunctionCall;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
 #[derive(Deserialize)]
 pub struct Query {
     pub repository: Repository,
     pub query: String,
+    pub conversation_id: Option<String>,
+    //Per-request GitHub token, read from the request headers
+    #[serde(skip)]
+    pub github_token: Option<String>,
 }
 
-impl ToString for Query {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let Query {
             repository:
                 Repository {
                     owner,
                     name,
                     branch,
+                    ..
                 },
             query,
+            ..
         } = self;
-        format!(
+        write!(
+            f,
             "##Repository Info##\nOwner:{}\nName:{}\nBranch:{}\n##User Query##\nQuery:{}",
             owner, name, branch, query
         )
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RelevantChunk {
     pub path: String,
     pub content: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    //The definition that the chunk is part of
+    pub symbol: Option<String>,
+    //The headings of the document section that the chunk is part of
+    pub headings: Vec<String>,
 }
 
-impl ToString for RelevantChunk {
-    fn to_string(&self) -> String {
-        format!(
-            "##Relevant file chunk##\nPath argument:{}\nRelevant content: {}",
-            self.path,
-            self.content.trim()
-        )
+impl std::fmt::Display for RelevantChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "##Relevant file chunk##\nPath argument:{}\nLines:{}-{}\n",
+            self.path, self.start_line, self.end_line,
+        )?;
+        if let Some(symbol) = &self.symbol {
+            writeln!(f, "Symbol:{}", symbol)?;
+        }
+        if !self.headings.is_empty() {
+            writeln!(f, "Section:{}", self.headings.join(" > "))?;
+        }
+        write!(f, "Relevant content: {}", self.content.trim())
+    }
+}
+
+impl From<&ChunkEmbeddings> for RelevantChunk {
+    fn from(chunk_embeddings: &ChunkEmbeddings) -> Self {
+        RelevantChunk {
+            path: chunk_embeddings.path.clone(),
+            content: chunk_embeddings.chunk.content.clone(),
+            start_line: chunk_embeddings.chunk.start_line,
+            end_line: chunk_embeddings.chunk.end_line,
+            symbol: chunk_embeddings.chunk.symbol.clone(),
+            headings: chunk_embeddings.chunk.headings.clone(),
+        }
     }
 }
 
+//A file chunk used as a source for the answer
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Source {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    //None for sources that can't be browsed, e.g. local repositories
+    pub url: Option<String>,
+    //Whether the answer refers to the path of the source, and to its lines if the answer cites lines of the file
+    pub referenced: bool,
+}
+
+pub fn sources(
+    relevant_chunks: &[RelevantChunk],
+    repository: &Repository,
+    commit_sha: Option<&str>,
+    answer: &str,
+) -> Vec<Source> {
+    //Link to the indexed commit, so that the line ranges stay accurate
+    let git_ref = commit_sha.unwrap_or(&repository.branch);
+    let source = repository.provider.source(None);
+
+    let mut sources: Vec<Source> = Vec::new();
+    for chunk in relevant_chunks {
+        let is_duplicate = sources.iter().any(|source| {
+            source.path == chunk.path
+                && source.start_line == chunk.start_line
+                && source.end_line == chunk.end_line
+        });
+        if is_duplicate {
+            continue;
+        }
+        sources.push(Source {
+            path: chunk.path.clone(),
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            url: source.file_url(
+                repository,
+                git_ref,
+                &chunk.path,
+                chunk.start_line,
+                chunk.end_line,
+            ),
+            referenced: path_citations(answer, &chunk.path)
+                .into_iter()
+                .any(|lines| match lines {
+                    Some((start_line, end_line)) => {
+                        start_line <= chunk.end_line && chunk.start_line <= end_line
+                    }
+                    None => true,
+                }),
+        });
+    }
+    sources
+}
+
+//The line ranges that the answer cites with each mention of the path, None for mentions without lines
+//Paths are matched as whole tokens, so that `main.rs` doesn't match `src/main.rs` or `main.rs.bak`
+fn path_citations(answer: &str, path: &str) -> Vec<Option<(usize, usize)>> {
+    answer
+        .match_indices(path)
+        .filter_map(|(index, _)| {
+            let before = &answer[..index];
+            let before = before.strip_suffix("./").unwrap_or(before);
+            let after = &answer[index + path.len()..];
+            let mut after_chars = after.chars();
+            //A `.` after the path may end the sentence
+            let continues = match after_chars.next() {
+                Some('.') => after_chars.next().is_some_and(char::is_alphanumeric),
+                Some(c) => is_path_char(c),
+                None => false,
+            };
+            if before.chars().next_back().is_some_and(is_path_char) || continues {
+                return None;
+            }
+            Some(cited_lines(after))
+        })
+        .collect()
+}
+
+fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '/' | '.' | '_' | '-')
+}
+
+//Lines cited right after a path, e.g. `src/main.rs:10-20`, `src/main.rs#L10-L20` or `src/main.rs` (lines 10-20)
+fn cited_lines(after: &str) -> Option<(usize, usize)> {
+    let after = after.trim_start_matches('`');
+    let range = match after.strip_prefix(':').or_else(|| after.strip_prefix("#L")) {
+        Some(range) => range,
+        None => {
+            let after = after.trim_start().trim_start_matches('(');
+            ["lines ", "Lines ", "line ", "Line "]
+                .iter()
+                .find_map(|prefix| after.strip_prefix(prefix))?
+        }
+    };
+    let (start_line, rest) = leading_number(range)?;
+    let end_line = rest
+        .strip_prefix(['-', '–'])
+        .map(|rest| rest.strip_prefix('L').unwrap_or(rest))
+        .and_then(leading_number)
+        .map_or(start_line, |(end_line, _)| end_line);
+    Some((start_line, end_line.max(start_line)))
+}
+
+fn leading_number(text: &str) -> Option<(usize, &str)> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    Some((text[..end].parse().ok()?, &text[end..]))
+}
+
 #[derive(Debug, Clone)]
 pub struct ParsedFunctionCall {
     pub name: Function,
@@ -61,3 +209,76 @@ impl TryFrom<&FunctionCall> for ParsedFunctionCall {
         Ok(ParsedFunctionCall { name, args })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Provider;
+
+    #[test]
+    fn test_sources() {
+        let chunk = |path: &str, start_line: usize, end_line: usize| RelevantChunk {
+            path: path.to_string(),
+            content: String::new(),
+            start_line,
+            end_line,
+            symbol: None,
+            headings: Vec::new(),
+        };
+        let relevant_chunks = vec![
+            chunk("src/main.rs", 1, 10),
+            chunk("src/main.rs", 1, 10),
+            chunk("README.md", 5, 8),
+        ];
+        let repository = Repository {
+            owner: "open-sauced".to_string(),
+            name: "ai".to_string(),
+            branch: "beta".to_string(),
+            provider: Provider::GitHub,
+        };
+
+        let sources = sources(
+            &relevant_chunks,
+            &repository,
+            Some("0123456789abcdef0123456789abcdef01234567"),
+            "The server is started in src/main.rs",
+        );
+
+        assert_eq!(
+            sources,
+            vec![
+                Source {
+                    path: "src/main.rs".to_string(),
+                    start_line: 1,
+                    end_line: 10,
+                    url: Some("https://github.com/open-sauced/ai/blob/0123456789abcdef0123456789abcdef01234567/src/main.rs#L1-L10".to_string()),
+                    referenced: true,
+                },
+                Source {
+                    path: "README.md".to_string(),
+                    start_line: 5,
+                    end_line: 8,
+                    url: Some("https://github.com/open-sauced/ai/blob/0123456789abcdef0123456789abcdef01234567/README.md#L5-L8".to_string()),
+                    referenced: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_path_citations() {
+        let answer = "The server starts in `src/main.rs` (lines 5-8), see src/main.rs#L20-L24 and ./src/main.rs:40.";
+        assert_eq!(
+            path_citations(answer, "src/main.rs"),
+            vec![Some((5, 8)), Some((20, 24)), Some((40, 40))]
+        );
+        assert_eq!(
+            path_citations("It's configured in src/config.rs.", "src/config.rs"),
+            vec![None]
+        );
+        //Only whole paths are matched
+        assert!(path_citations(answer, "main.rs").is_empty());
+        assert!(path_citations("See src/main.rs.bak", "src/main.rs").is_empty());
+        assert!(path_citations("See lib/src/main.rs", "src/main.rs").is_empty());
+    }
+}
@@ -1,69 +1,112 @@
 #![allow(unused_must_use)]
 mod data;
 mod prompts;
+mod session;
 
 use crate::{
     constants::{RELEVANT_CHUNKS_LIMIT, RELEVANT_FILES_LIMIT},
+    contents::{ContentStore, Snapshot},
     db::RepositoryEmbeddingsDB,
     embeddings::EmbeddingsModel,
+    llm::{ChatBackend, ChatCompletion},
     prelude::*,
-    routes::events::{emit, QueryEvent},
+    routes::events::{emit, EventSender, QueryEvent},
 };
-use actix_web_lab::sse::Sender;
 pub use data::*;
-use openai_api_rs::v1::chat_completion::{FinishReason, FunctionCallType};
-use openai_api_rs::v1::{
-    api::Client,
-    chat_completion::{
-        ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, MessageRole,
-    },
+use openai_api_rs::v1::chat_completion::{
+    ChatCompletionMessage, ChatCompletionRequest, FunctionCallType, MessageRole,
 };
-use std::env;
+use serde_json::json;
+pub use session::*;
 use std::sync::Arc;
 
 use prompts::{generate_completion_request, system_message};
 
 use self::prompts::{answer_generation_prompt, sanitize_query_prompt};
 
+use crate::utils::errors::RequestError;
 use crate::utils::functions::{
     paths_to_completion_message, relevant_chunks_to_completion_message, search_codebase,
     search_file, search_path, Function,
 };
 
-pub struct Conversation<D: RepositoryEmbeddingsDB, M: EmbeddingsModel> {
+pub struct Conversation<D: RepositoryEmbeddingsDB + ?Sized, M: EmbeddingsModel, C: ChatBackend> {
     query: Query,
-    client: Client,
+    chat: Arc<C>,
     messages: Vec<ChatCompletionMessage>,
+    relevant_chunks: Vec<RelevantChunk>,
+    //Commit SHA of the indexed repository, used to link the sources and read unindexed files
+    commit_sha: Option<String>,
+    //Files of the repository at the indexed commit, if they were stored
+    snapshot: Option<Snapshot>,
     db: Arc<D>,
     model: Arc<M>,
-    sender: Sender,
+    sender: EventSender,
 }
 
-impl<D: RepositoryEmbeddingsDB, M: EmbeddingsModel> Conversation<D, M> {
+impl<D: RepositoryEmbeddingsDB + ?Sized, M: EmbeddingsModel, C: ChatBackend> Conversation<D, M, C> {
+    #[allow(clippy::too_many_arguments)]
     pub async fn initiate(
         mut query: Query,
+        id: String,
+        session: Option<ConversationSession>,
         db: Arc<D>,
         model: Arc<M>,
-        sender: Sender,
+        chat: Arc<C>,
+        contents: &ContentStore,
+        sender: EventSender,
     ) -> Result<Self> {
-        emit(&sender, QueryEvent::ProcessQuery(None)).await;
-        query.query = sanitize_query(&query.query)?;
+        emit(
+            &sender,
+            QueryEvent::ProcessQuery(Some(json!({ "conversation_id": id }))),
+        )
+        .await;
+        query.query = sanitize_query(&query.query, chat.as_ref()).await?;
+        let commit_sha = db
+            .get_repo_metadata(&query.repository)
+            .await?
+            .and_then(|metadata| metadata.commit_sha);
+        let snapshot = match commit_sha.clone() {
+            Some(commit_sha) => {
+                let contents = contents.clone();
+                let repository = query.repository.clone();
+                actix_rt::task::spawn_blocking(move || contents.snapshot(&repository, &commit_sha))
+                    .await?
+            }
+            None => None,
+        };
+
+        let system_message = ChatCompletionMessage {
+            name: None,
+            function_call: None,
+            role: MessageRole::system,
+            content: system_message(),
+        };
+        let (mut messages, relevant_chunks) = match session {
+            //Continue from the previous turn with the retrieval system prompt restored
+            Some(ConversationSession {
+                mut messages,
+                relevant_chunks,
+                ..
+            }) => {
+                messages[0] = system_message;
+                (messages, relevant_chunks)
+            }
+            None => (vec![system_message], Vec::new()),
+        };
+        messages.push(ChatCompletionMessage {
+            name: None,
+            function_call: None,
+            role: MessageRole::user,
+            content: query.to_string(),
+        });
+
         Ok(Self {
-            client: Client::new(env::var("OPENAI_API_KEY").unwrap()),
-            messages: vec![
-                ChatCompletionMessage {
-                    name: None,
-                    function_call: None,
-                    role: MessageRole::system,
-                    content: system_message(),
-                },
-                ChatCompletionMessage {
-                    name: None,
-                    function_call: None,
-                    role: MessageRole::user,
-                    content: query.to_string(),
-                },
-            ],
+            chat,
+            messages,
+            relevant_chunks,
+            commit_sha,
+            snapshot,
             query,
             db,
             model,
@@ -71,10 +114,43 @@ impl<D: RepositoryEmbeddingsDB, M: EmbeddingsModel> Conversation<D, M> {
         })
     }
 
+    //The state to be stored for the next turn of this conversation
+    pub fn into_session(self) -> ConversationSession {
+        ConversationSession {
+            repository: self.query.repository,
+            messages: self.messages,
+            relevant_chunks: self.relevant_chunks,
+        }
+    }
+
     fn append_message(&mut self, message: ChatCompletionMessage) {
         self.messages.push(message);
     }
 
+    //Records the answer in the history and emits it with the sources retrieved during the conversation
+    async fn finish(&mut self, answer: String) {
+        self.append_message(ChatCompletionMessage {
+            name: None,
+            function_call: None,
+            role: MessageRole::assistant,
+            content: answer.clone(),
+        });
+        let sources = sources(
+            &self.relevant_chunks,
+            &self.query.repository,
+            self.commit_sha.as_deref(),
+            &answer,
+        );
+        emit(
+            &self.sender,
+            QueryEvent::Done(Some(json!({
+                "answer": answer,
+                "sources": sources,
+            }))),
+        )
+        .await;
+    }
+
     fn prepare_final_explanation_message(&mut self) {
         //Update the system prompt using answer_generation_prompt()
         self.messages[0] = ChatCompletionMessage {
@@ -85,8 +161,23 @@ impl<D: RepositoryEmbeddingsDB, M: EmbeddingsModel> Conversation<D, M> {
         }
     }
 
-    fn send_request(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
-        Ok(self.client.chat_completion(request)?)
+    //Emits each delta of the streamed response and returns the assembled text
+    async fn stream_response(&self, request: ChatCompletionRequest) -> Result<String> {
+        let mut stream = self
+            .chat
+            .stream_chat_completion(request)
+            .await
+            .map_err(RequestError::upstream)?;
+        let mut response = String::new();
+        while let Some(delta) = stream.next_delta().await.map_err(RequestError::upstream)? {
+            emit(
+                &self.sender,
+                QueryEvent::AnswerDelta(Some(delta.clone().into())),
+            )
+            .await;
+            response.push_str(&delta);
+        }
+        Ok(response)
     }
 
     pub async fn generate(&mut self) -> Result<()> {
@@ -96,135 +187,129 @@ impl<D: RepositoryEmbeddingsDB, M: EmbeddingsModel> Conversation<D, M> {
             let request =
                 generate_completion_request(self.messages.clone(), FunctionCallType::Auto);
 
-            match self.send_request(request) {
-                Ok(response) => {
-                    match response.choices[0].finish_reason {
-                        Some(FinishReason::function_call) => {
-                            if let Some(function_call) =
-                                response.choices[0].message.function_call.clone()
-                            {
-                                let parsed_function_call =
-                                    ParsedFunctionCall::try_from(&function_call)?;
-                                let function_call_message = ChatCompletionMessage {
-                                    name: None,
-                                    function_call: Some(function_call),
-                                    role: MessageRole::assistant,
-                                    content: String::new(),
-                                };
-                                self.append_message(function_call_message);
-                                dbg!(parsed_function_call.clone());
-                                match parsed_function_call.name {
-                                    Function::SearchCodebase => {
-                                        let query: &str = parsed_function_call.args["query"]
-                                            .as_str()
-                                            .unwrap_or_default();
-                                        emit(
-                                            &self.sender,
-                                            QueryEvent::SearchCodebase(Some(
-                                                parsed_function_call.clone().args,
-                                            )),
-                                        )
-                                        .await;
-                                        let relevant_chunks = search_codebase(
-                                            query,
-                                            &self.query.repository,
-                                            self.model.as_ref(),
-                                            self.db.as_ref(),
-                                            RELEVANT_FILES_LIMIT,
-                                            RELEVANT_CHUNKS_LIMIT,
-                                        )
-                                        .await?;
-                                        let completion_message =
-                                            relevant_chunks_to_completion_message(
-                                                parsed_function_call.name,
-                                                relevant_chunks,
-                                            );
-                                        self.append_message(completion_message);
-                                    }
-                                    Function::SearchFile => {
-                                        let query: &str = parsed_function_call.args["query"]
-                                            .as_str()
-                                            .unwrap_or_default();
-                                        let path: &str = parsed_function_call.args["path"]
-                                            .as_str()
-                                            .unwrap_or_default();
-                                        emit(
-                                            &self.sender,
-                                            QueryEvent::SearchFile(Some(
-                                                parsed_function_call.clone().args,
-                                            )),
-                                        )
-                                        .await;
-                                        let relevant_chunks = search_file(
-                                            path,
-                                            query,
-                                            &self.query.repository,
-                                            self.model.as_ref(),
-                                            RELEVANT_CHUNKS_LIMIT,
-                                        )
-                                        .await?;
-                                        let completion_message =
-                                            relevant_chunks_to_completion_message(
-                                                parsed_function_call.name,
-                                                relevant_chunks,
-                                            );
-                                        self.append_message(completion_message);
-                                    }
-                                    Function::SearchPath => {
-                                        let path: &str = parsed_function_call.args["path"]
-                                            .as_str()
-                                            .unwrap_or_default();
-                                        emit(
-                                            &self.sender,
-                                            QueryEvent::SearchPath(Some(
-                                                parsed_function_call.clone().args,
-                                            )),
-                                        )
-                                        .await;
-                                        let fuzzy_matched_paths = search_path(
-                                            path,
-                                            &self.query.repository,
-                                            self.db.as_ref(),
-                                            1,
-                                        )
-                                        .await?;
-                                        let completion_message = paths_to_completion_message(
-                                            parsed_function_call.name,
-                                            fuzzy_matched_paths,
-                                        );
-                                        self.append_message(completion_message);
-                                    }
-                                    Function::Done => {
-                                        self.prepare_final_explanation_message();
-
-                                        //Generate a request with the message history and no functions
-                                        let request = generate_completion_request(
-                                            self.messages.clone(),
-                                            FunctionCallType::None,
-                                        );
-                                        emit(&self.sender, QueryEvent::GenerateResponse(None))
-                                            .await;
-                                        let response = match self.send_request(request) {
-                                            Ok(response) => response,
-                                            Err(e) => {
-                                                dbg!(e.to_string());
-                                                return Err(e);
-                                            }
-                                        };
-                                        let response = response.choices[0]
-                                            .message
-                                            .content
-                                            .clone()
-                                            .unwrap_or_default();
-                                        emit(&self.sender, QueryEvent::Done(Some(response.into())))
-                                            .await;
-                                        return Ok(());
-                                    }
-                                }
+            match self.chat.chat_completion(request).await {
+                Ok(completion) => {
+                    match completion {
+                        ChatCompletion::FunctionCall(function_call) => {
+                            let parsed_function_call = ParsedFunctionCall::try_from(&function_call)
+                                .map_err(RequestError::upstream)?;
+                            let function_call_message = ChatCompletionMessage {
+                                name: None,
+                                function_call: Some(function_call),
+                                role: MessageRole::assistant,
+                                content: String::new(),
                             };
+                            self.append_message(function_call_message);
+                            dbg!(parsed_function_call.clone());
+                            match parsed_function_call.name {
+                                Function::SearchCodebase => {
+                                    let query: &str = parsed_function_call.args["query"]
+                                        .as_str()
+                                        .unwrap_or_default();
+                                    emit(
+                                        &self.sender,
+                                        QueryEvent::SearchCodebase(Some(
+                                            parsed_function_call.clone().args,
+                                        )),
+                                    )
+                                    .await;
+                                    let relevant_chunks = search_codebase(
+                                        query,
+                                        &self.query.repository,
+                                        self.model.as_ref(),
+                                        self.db.as_ref(),
+                                        self.snapshot.as_ref(),
+                                        RELEVANT_FILES_LIMIT,
+                                        RELEVANT_CHUNKS_LIMIT,
+                                    )
+                                    .await?;
+                                    self.relevant_chunks.extend(relevant_chunks.clone());
+                                    let completion_message = relevant_chunks_to_completion_message(
+                                        parsed_function_call.name,
+                                        relevant_chunks,
+                                    );
+                                    self.append_message(completion_message);
+                                }
+                                Function::SearchFile => {
+                                    let query: &str = parsed_function_call.args["query"]
+                                        .as_str()
+                                        .unwrap_or_default();
+                                    let path: &str = parsed_function_call.args["path"]
+                                        .as_str()
+                                        .unwrap_or_default();
+                                    emit(
+                                        &self.sender,
+                                        QueryEvent::SearchFile(Some(
+                                            parsed_function_call.clone().args,
+                                        )),
+                                    )
+                                    .await;
+                                    let relevant_chunks = search_file(
+                                        path,
+                                        query,
+                                        &self.query.repository,
+                                        self.model.as_ref(),
+                                        self.db.as_ref(),
+                                        self.snapshot.as_ref(),
+                                        RELEVANT_CHUNKS_LIMIT,
+                                        self.commit_sha.as_deref(),
+                                        self.query.github_token.as_deref(),
+                                    )
+                                    .await?;
+                                    self.relevant_chunks.extend(relevant_chunks.clone());
+                                    let completion_message = relevant_chunks_to_completion_message(
+                                        parsed_function_call.name,
+                                        relevant_chunks,
+                                    );
+                                    self.append_message(completion_message);
+                                }
+                                Function::SearchPath => {
+                                    let path: &str = parsed_function_call.args["path"]
+                                        .as_str()
+                                        .unwrap_or_default();
+                                    emit(
+                                        &self.sender,
+                                        QueryEvent::SearchPath(Some(
+                                            parsed_function_call.clone().args,
+                                        )),
+                                    )
+                                    .await;
+                                    let fuzzy_matched_paths = search_path(
+                                        path,
+                                        &self.query.repository,
+                                        self.db.as_ref(),
+                                        1,
+                                    )
+                                    .await?;
+                                    let completion_message = paths_to_completion_message(
+                                        parsed_function_call.name,
+                                        fuzzy_matched_paths,
+                                    );
+                                    self.append_message(completion_message);
+                                }
+                                Function::Done => {
+                                    self.prepare_final_explanation_message();
+
+                                    //Generate a request with the message history and no functions
+                                    let request = generate_completion_request(
+                                        self.messages.clone(),
+                                        FunctionCallType::None,
+                                    );
+                                    emit(&self.sender, QueryEvent::GenerateResponse(None)).await;
+                                    let response = match self.stream_response(request).await {
+                                        Ok(response) => response,
+                                        Err(e) => {
+                                            dbg!(e.to_string());
+                                            return Err(e);
+                                        }
+                                    };
+                                    self.finish(response).await;
+                                    return Ok(());
+                                }
+                            }
                         }
 
-                        Some(FinishReason::stop) => {
+                        ChatCompletion::Message(response) => {
                             //As of yet, there isn't a robust way to instruct the model to respond with function calls only except for switching to GPT-4
                             //We can only suggest it do so in the system message
                             // prompts.rs#L127
@@ -232,50 +317,319 @@ impl<D: RepositoryEmbeddingsDB, M: EmbeddingsModel> Conversation<D, M> {
                             // "gpt-3.5-turbo-0301 does not always pay strong attention to system messages. Future models will be trained to pay strong attention to system messages."
                             // "If you are using GPT-3.5-turbo, you can already utilize the system role input; however, be aware that it will not pay strong attention to it. On the other hand, if you have access to the GPT-4 preview, you can take full advantage of this powerful feature."
 
-                            let response = response.choices[0]
-                                .message
-                                .content
-                                .clone()
-                                .unwrap_or_default();
-                            emit(&self.sender, QueryEvent::Done(Some(response.into()))).await;
+                            self.finish(response).await;
                             return Ok(());
                         }
-
-                        _ => {
-                            return Err(anyhow::anyhow!("Model returned an unexpected response."));
-                        }
                     }
                 }
                 Err(e) => {
-                    return Err(e);
+                    return Err(RequestError::upstream(e));
                 }
             };
         }
     }
 }
 
-fn sanitize_query(query: &str) -> Result<String> {
+async fn sanitize_query<C: ChatBackend>(query: &str, chat: &C) -> Result<String> {
     let message = ChatCompletionMessage {
         name: None,
         function_call: None,
         role: MessageRole::user,
         content: sanitize_query_prompt(query),
     };
-    let client = Client::new(env::var("OPENAI_API_KEY")?);
     let request = generate_completion_request(vec![message], FunctionCallType::None);
-    let response = client.chat_completion(request)?;
-    if let Some(FinishReason::stop) = response.choices[0].finish_reason {
-        let sanitized_query = response.choices[0]
-            .message
-            .content
-            .clone()
-            .unwrap_or_default();
-        if sanitized_query.is_empty() {
-            Err(anyhow::anyhow!("No query found"))
-        } else {
-            Ok(sanitized_query)
+    match chat.chat_completion(request).await {
+        Ok(ChatCompletion::Message(sanitized_query)) => {
+            if sanitized_query.is_empty() {
+                Err(RequestError::bad_input(anyhow::anyhow!("No query found")))
+            } else {
+                Ok(sanitized_query)
+            }
+        }
+        _ => Err(RequestError::upstream(anyhow::anyhow!(
+            "Query sanitization failed"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunker::{tests::sizer, ChunkSizer, FileChunk},
+        db::InMemoryDB,
+        embeddings::Embeddings,
+        github::{ChunkEmbeddings, Provider, Repository, RepositoryEmbeddings, RepositoryMetadata},
+        llm::mock::MockBackend,
+        routes::events::EventRecord,
+        utils::errors::RequestErrorKind,
+    };
+    use openai_api_rs::v1::chat_completion::FunctionCall;
+    use serde_json::Value;
+
+    const COMMIT_SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn repository() -> Repository {
+        Repository {
+            owner: "open-sauced".to_string(),
+            name: "ai".to_string(),
+            branch: "beta".to_string(),
+            provider: Provider::GitHub,
+        }
+    }
+
+    fn chunk() -> RelevantChunk {
+        RelevantChunk {
+            path: "src/main.rs".to_string(),
+            content: "fn main() {}".to_string(),
+            start_line: 1,
+            end_line: 3,
+            symbol: Some("main".to_string()),
+            headings: Vec::new(),
+        }
+    }
+
+    //Indexed repository with a single chunk
+    async fn db() -> InMemoryDB {
+        let repository = repository();
+        let RelevantChunk {
+            path,
+            content,
+            start_line,
+            end_line,
+            symbol,
+            headings,
+        } = chunk();
+        let db = InMemoryDB::default();
+        db.insert_repo_embeddings(RepositoryEmbeddings {
+            repo_id: repository.to_string(),
+            chunk_embeddings: vec![ChunkEmbeddings {
+                path,
+                hash: String::new(),
+                chunk: FileChunk {
+                    content,
+                    start_line,
+                    end_line,
+                    symbol,
+                    headings,
+                },
+                embeddings: vec![1.0],
+            }],
+            deleted_paths: vec![],
+            metadata: RepositoryMetadata {
+                owner: repository.owner,
+                name: repository.name,
+                branch: repository.branch,
+                provider: repository.provider,
+                file_count: 1,
+                indexed_at: 0,
+                embedding_model: "stub".to_string(),
+                commit_sha: Some(COMMIT_SHA.to_string()),
+                rules: Default::default(),
+                private: false,
+                index: Default::default(),
+            },
+        })
+        .await
+        .unwrap();
+        db
+    }
+
+    //Without a snapshot of the repository, so that chunks are returned as indexed
+    fn contents() -> ContentStore {
+        ContentStore::open(std::env::temp_dir().join("repo-query-empty-contents")).unwrap()
+    }
+
+    struct StubModel(ChunkSizer);
+
+    impl EmbeddingsModel for StubModel {
+        fn model_name(&self) -> &str {
+            "stub"
+        }
+
+        fn chunk_sizer(&self) -> &ChunkSizer {
+            &self.0
+        }
+
+        fn embed<S: AsRef<str> + Send + Sync>(&self, texts: Vec<S>) -> Result<Vec<Embeddings>> {
+            Ok(texts.iter().map(|_| vec![1.0]).collect())
+        }
+
+        fn query_embed<S: AsRef<str> + Send + Sync>(&self, _: S) -> Result<Embeddings> {
+            Ok(vec![1.0])
+        }
+    }
+
+    async fn run(script: Vec<ChatCompletion>) -> (Result<()>, Vec<EventRecord>, Vec<Value>) {
+        let sender = EventSender::collector();
+        let query = Query {
+            repository: repository(),
+            query: "Where is the server started?".to_string(),
+            conversation_id: None,
+            github_token: None,
+        };
+        let conversation = Conversation::initiate(
+            query,
+            "id".to_string(),
+            None,
+            Arc::new(db().await),
+            Arc::new(StubModel(sizer())),
+            Arc::new(MockBackend::new(script)),
+            &contents(),
+            sender.clone(),
+        )
+        .await;
+        let (result, messages) = match conversation {
+            Ok(mut conversation) => {
+                let result = conversation.generate().await;
+                let messages = conversation
+                    .into_session()
+                    .messages
+                    .iter()
+                    .map(|message| json!(message))
+                    .collect();
+                (result, messages)
+            }
+            Err(e) => (Err(e), Vec::new()),
+        };
+        (result, sender.collected(), messages)
+    }
+
+    fn event(event: &'static str, data: Option<Value>) -> EventRecord {
+        EventRecord { event, data }
+    }
+
+    fn function_call_message(function: Function, arguments: Value) -> Value {
+        json!(ChatCompletionMessage {
+            name: None,
+            function_call: Some(FunctionCall {
+                name: Some(function.to_string()),
+                arguments: Some(arguments.to_string()),
+            }),
+            role: MessageRole::assistant,
+            content: String::new(),
+        })
+    }
+
+    fn function_message(function: Function, content: String) -> Value {
+        json!(ChatCompletionMessage {
+            name: Some(function.to_string()),
+            function_call: None,
+            role: MessageRole::function,
+            content,
+        })
+    }
+
+    #[actix_rt::test]
+    async fn test_generate_with_function_calls() {
+        let search_codebase_args = json!({ "query": "server" });
+        let search_path_args = json!({ "path": "main" });
+        let search_file_args = json!({ "path": "src/main.rs", "query": "server" });
+        let (result, events, messages) = run(vec![
+            MockBackend::message("Where is the server started?"),
+            MockBackend::function_call(Function::SearchCodebase, search_codebase_args.clone()),
+            MockBackend::function_call(Function::SearchPath, search_path_args.clone()),
+            MockBackend::function_call(Function::SearchFile, search_file_args.clone()),
+            MockBackend::function_call(Function::Done, json!({})),
+            MockBackend::message("In src/main.rs"),
+        ])
+        .await;
+        result.unwrap();
+
+        let answer_sources = sources(
+            &[chunk(), chunk()],
+            &repository(),
+            Some(COMMIT_SHA),
+            "In src/main.rs",
+        );
+        assert_eq!(
+            events,
+            vec![
+                event("PROCESS_QUERY", Some(json!({ "conversation_id": "id" }))),
+                event("SEARCH_CODEBASE", Some(search_codebase_args.clone())),
+                event("SEARCH_PATH", Some(search_path_args.clone())),
+                event("SEARCH_FILE", Some(search_file_args.clone())),
+                event("GENERATE_RESPONSE", None),
+                event("ANSWER_DELTA", Some("In ".into())),
+                event("ANSWER_DELTA", Some("src/main.rs".into())),
+                event(
+                    "DONE",
+                    Some(json!({ "answer": "In src/main.rs", "sources": answer_sources })),
+                ),
+            ]
+        );
+
+        let user_message = Query {
+            repository: repository(),
+            query: "Where is the server started?".to_string(),
+            conversation_id: None,
+            github_token: None,
         }
-    } else {
-        Err(anyhow::anyhow!("Query sanitization failed"))
+        .to_string();
+        assert_eq!(
+            messages,
+            vec![
+                json!({ "role": "system", "content": answer_generation_prompt() }),
+                json!({ "role": "user", "content": user_message }),
+                function_call_message(Function::SearchCodebase, search_codebase_args),
+                function_message(Function::SearchCodebase, chunk().to_string()),
+                function_call_message(Function::SearchPath, search_path_args),
+                function_message(Function::SearchPath, "src/main.rs".to_string()),
+                function_call_message(Function::SearchFile, search_file_args),
+                function_message(Function::SearchFile, chunk().to_string()),
+                function_call_message(Function::Done, json!({})),
+                json!({ "role": "assistant", "content": "In src/main.rs" }),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_generate_with_direct_answer() {
+        let (result, events, messages) = run(vec![
+            MockBackend::message("What is this repository?"),
+            MockBackend::message("An AI service"),
+        ])
+        .await;
+        result.unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                event("PROCESS_QUERY", Some(json!({ "conversation_id": "id" }))),
+                event(
+                    "DONE",
+                    Some(json!({ "answer": "An AI service", "sources": [] })),
+                ),
+            ]
+        );
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["content"], json!(system_message()));
+        assert_eq!(
+            messages[2],
+            json!({ "role": "assistant", "content": "An AI service" })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_generate_with_failed_sanitization() {
+        let (result, events, _) =
+            run(vec![MockBackend::function_call(Function::Done, json!({}))]).await;
+
+        let error = result.unwrap_err();
+        assert_eq!(error.to_string(), "Query sanitization failed");
+        assert_eq!(RequestError::kind(&error), Some(RequestErrorKind::Upstream));
+        assert_eq!(
+            events,
+            vec![event(
+                "PROCESS_QUERY",
+                Some(json!({ "conversation_id": "id" }))
+            )]
+        );
+
+        //A query without a question is bad input
+        let (result, _, _) = run(vec![MockBackend::message("")]).await;
+        let error = result.unwrap_err();
+        assert_eq!(error.to_string(), "No query found");
+        assert_eq!(RequestError::kind(&error), Some(RequestErrorKind::BadInput));
     }
 }
@@ -0,0 +1,262 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use openai_api_rs::v1::chat_completion::{ChatCompletionMessage, MessageRole};
+
+use crate::{
+    constants::{
+        CONVERSATION_CHUNKS_LIMIT, CONVERSATION_HISTORY_LIMIT, CONVERSATION_STORE_CAPACITY,
+        CONVERSATION_TTL_SECS,
+    },
+    github::Repository,
+};
+
+use super::RelevantChunk;
+
+//The state of a conversation that is carried over to the next turn
+#[derive(Debug, Clone)]
+pub struct ConversationSession {
+    pub repository: Repository,
+    pub messages: Vec<ChatCompletionMessage>,
+    pub relevant_chunks: Vec<RelevantChunk>,
+}
+
+struct StoredSession {
+    session: ConversationSession,
+    last_accessed: Instant,
+}
+
+//In-memory store of conversation sessions
+//Sessions expire after `ttl` of inactivity and the least recently used session is evicted once `capacity` is reached
+pub struct ConversationStore {
+    sessions: Mutex<HashMap<String, StoredSession>>,
+    //IDs of the conversations with a turn in progress
+    in_flight: Mutex<HashSet<String>>,
+    ttl: Duration,
+    capacity: usize,
+}
+
+impl Default for ConversationStore {
+    fn default() -> Self {
+        Self::new(
+            Duration::from_secs(CONVERSATION_TTL_SECS),
+            CONVERSATION_STORE_CAPACITY,
+        )
+    }
+}
+
+impl ConversationStore {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashSet::new()),
+            ttl,
+            capacity,
+        }
+    }
+
+    pub fn generate_id() -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    //Marks a turn of the conversation as in progress until the returned guard is dropped
+    //None if a turn of the conversation is already in progress, since both turns would continue from the same history
+    pub fn begin_turn(self: &Arc<Self>, id: &str) -> Option<TurnGuard> {
+        let is_idle = self.in_flight.lock().unwrap().insert(id.to_string());
+        is_idle.then(|| TurnGuard {
+            store: self.clone(),
+            id: id.to_string(),
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<ConversationSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.remove_expired(&mut sessions);
+        sessions.get_mut(id).map(|stored| {
+            stored.last_accessed = Instant::now();
+            stored.session.clone()
+        })
+    }
+
+    pub fn insert(&self, id: String, mut session: ConversationSession) {
+        truncate_session(&mut session);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        self.remove_expired(&mut sessions);
+
+        if !sessions.contains_key(&id) && sessions.len() >= self.capacity {
+            let least_recently_used = sessions
+                .iter()
+                .min_by_key(|(_, stored)| stored.last_accessed)
+                .map(|(id, _)| id.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                sessions.remove(&least_recently_used);
+            }
+        }
+
+        sessions.insert(
+            id,
+            StoredSession {
+                session,
+                last_accessed: Instant::now(),
+            },
+        );
+    }
+
+    fn remove_expired(&self, sessions: &mut HashMap<String, StoredSession>) {
+        sessions.retain(|_, stored| stored.last_accessed.elapsed() < self.ttl);
+    }
+}
+
+pub struct TurnGuard {
+    store: Arc<ConversationStore>,
+    id: String,
+}
+
+impl Drop for TurnGuard {
+    fn drop(&mut self) {
+        self.store.in_flight.lock().unwrap().remove(&self.id);
+    }
+}
+
+//Keep the system message and the most recent history within the limits
+//The history is cut at the start of a turn, a user message, so that function results stay with their function calls
+//If the latest turn doesn't fit, it's cut before an assistant message instead
+fn truncate_session(session: &mut ConversationSession) {
+    let messages = &mut session.messages;
+    if messages.len() > CONVERSATION_HISTORY_LIMIT {
+        let earliest = messages.len() - CONVERSATION_HISTORY_LIMIT + 1;
+        let boundary = |is_boundary: fn(&MessageRole) -> bool| {
+            messages[earliest..]
+                .iter()
+                .position(|message| is_boundary(&message.role))
+        };
+        let start = boundary(|role| matches!(role, MessageRole::user))
+            .or_else(|| boundary(|role| !matches!(role, MessageRole::function)))
+            .map_or(messages.len(), |index| earliest + index);
+        messages.drain(1..start);
+    }
+
+    let chunks = &mut session.relevant_chunks;
+    if chunks.len() > CONVERSATION_CHUNKS_LIMIT {
+        chunks.drain(..chunks.len() - CONVERSATION_CHUNKS_LIMIT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Provider;
+    use openai_api_rs::v1::chat_completion::MessageRole;
+
+    fn session(messages_count: usize) -> ConversationSession {
+        ConversationSession {
+            repository: Repository {
+                owner: "open-sauced".to_string(),
+                name: "ai".to_string(),
+                branch: "beta".to_string(),
+                provider: Provider::GitHub,
+            },
+            messages: (0..messages_count)
+                .map(|i| ChatCompletionMessage {
+                    name: None,
+                    function_call: None,
+                    role: MessageRole::user,
+                    content: i.to_string(),
+                })
+                .collect(),
+            relevant_chunks: vec![],
+        }
+    }
+
+    #[test]
+    fn test_store_get_insert() {
+        let store = ConversationStore::default();
+        assert!(store.get("missing").is_none());
+
+        store.insert("id".to_string(), session(2));
+        let stored = store.get("id").unwrap();
+        assert_eq!(stored.messages.len(), 2);
+    }
+
+    #[test]
+    fn test_store_ttl() {
+        let store = ConversationStore::new(Duration::ZERO, 10);
+        store.insert("id".to_string(), session(2));
+        assert!(store.get("id").is_none());
+    }
+
+    #[test]
+    fn test_store_capacity() {
+        let store = ConversationStore::new(Duration::from_secs(60), 2);
+        store.insert("first".to_string(), session(1));
+        store.insert("second".to_string(), session(1));
+        store.get("first");
+        store.insert("third".to_string(), session(1));
+
+        assert!(store.get("first").is_some());
+        assert!(store.get("second").is_none());
+        assert!(store.get("third").is_some());
+    }
+
+    #[test]
+    fn test_history_truncation() {
+        let store = ConversationStore::default();
+        store.insert("id".to_string(), session(CONVERSATION_HISTORY_LIMIT + 10));
+        let messages = store.get("id").unwrap().messages;
+
+        assert_eq!(messages.len(), CONVERSATION_HISTORY_LIMIT);
+        //The system message is always retained
+        assert_eq!(messages[0].content, "0");
+        assert_eq!(messages[1].content, "11");
+    }
+
+    #[test]
+    fn test_history_truncation_at_turns() {
+        let message = |role, content: &str| ChatCompletionMessage {
+            name: None,
+            function_call: None,
+            role,
+            content: content.to_string(),
+        };
+        //Turns of a query, a function call, its result and the answer
+        let mut turns = session(1);
+        for turn in 0..CONVERSATION_HISTORY_LIMIT {
+            turns.messages.extend([
+                message(MessageRole::user, &format!("query {turn}")),
+                message(MessageRole::assistant, ""),
+                message(MessageRole::function, "result"),
+                message(MessageRole::assistant, "answer"),
+            ]);
+        }
+        truncate_session(&mut turns);
+        let messages = turns.messages;
+        assert!(messages.len() <= CONVERSATION_HISTORY_LIMIT);
+        assert_eq!(messages[0].content, "0");
+        assert!(matches!(messages[1].role, MessageRole::user));
+
+        //A turn longer than the limit keeps its function calls with their results
+        let mut long_turn = session(1);
+        for _ in 0..CONVERSATION_HISTORY_LIMIT {
+            long_turn.messages.extend([
+                message(MessageRole::assistant, ""),
+                message(MessageRole::function, "result"),
+            ]);
+        }
+        truncate_session(&mut long_turn);
+        assert!(long_turn.messages.len() <= CONVERSATION_HISTORY_LIMIT);
+        assert!(matches!(long_turn.messages[1].role, MessageRole::assistant));
+    }
+
+    #[test]
+    fn test_one_turn_in_progress_per_conversation() {
+        let store = Arc::new(ConversationStore::default());
+        let turn = store.begin_turn("id").unwrap();
+        assert!(store.begin_turn("id").is_none());
+        assert!(store.begin_turn("other").is_some());
+
+        drop(turn);
+        assert!(store.begin_turn("id").is_some());
+    }
+}
@@ -0,0 +1,591 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::{lexical::LexicalIndex, memory::group_chunks, RepositoryEmbeddingsDB};
+use crate::{
+    constants::{
+        EMBEDDED_DB_FILE_MAGIC, EMBEDDED_DB_FORMAT_VERSION, EMBEDDED_DB_PATH_DEFAULT,
+        EMBEDDED_INDEX_ITERATIONS, EMBEDDED_INDEX_MIN_CHUNKS, EMBEDDED_INDEX_PROBES,
+    },
+    conversation::RelevantChunk,
+    embeddings::{cosine_similarity, Embeddings},
+    github::{
+        ChunkEmbeddings, Repository, RepositoryEmbeddings, RepositoryFilePaths, RepositoryMetadata,
+    },
+    prelude::*,
+};
+use async_trait::async_trait;
+use rayon::prelude::*;
+
+//Embeddings persisted to a local data directory, one file per repository
+//Collections are loaded on startup and written to disk on every change
+//Only the metadata and chunks are stored, the indexes derived from them are rebuilt when loaded
+pub struct EmbeddedDB {
+    path: PathBuf,
+    collections: RwLock<HashMap<String, Arc<Collection>>>,
+}
+
+#[derive(Clone)]
+struct Collection {
+    metadata: RepositoryMetadata,
+    chunks: Vec<ChunkEmbeddings>,
+    index: ClusterIndex,
+    lexical: LexicalIndex,
+}
+
+#[async_trait]
+impl RepositoryEmbeddingsDB for EmbeddedDB {
+    async fn insert_repo_embeddings(&self, repo: RepositoryEmbeddings) -> Result<()> {
+        let RepositoryEmbeddings {
+            repo_id,
+            chunk_embeddings,
+            metadata,
+            ..
+        } = repo;
+
+        let path = self.collection_path(&repo_id);
+        let collection = actix_rt::task::spawn_blocking(move || {
+            let collection = Collection::new(metadata, chunk_embeddings);
+            save(&path, &collection)?;
+            Ok::<_, anyhow::Error>(collection)
+        })
+        .await??;
+        self.collections
+            .write()
+            .unwrap()
+            .insert(repo_id, Arc::new(collection));
+        Ok(())
+    }
+
+    async fn update_repo_embeddings(&self, repo: RepositoryEmbeddings) -> Result<()> {
+        let RepositoryEmbeddings {
+            repo_id,
+            chunk_embeddings,
+            deleted_paths,
+            metadata,
+        } = repo;
+
+        let collection = self
+            .collections
+            .read()
+            .unwrap()
+            .get(&repo_id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Repository is not indexed"))?;
+        //The updated collection replaces the current one once it's saved, so that a failed save leaves both unchanged
+        let path = self.collection_path(&repo_id);
+        let collection = actix_rt::task::spawn_blocking(move || {
+            let mut collection = Collection::clone(&collection);
+            collection.update(metadata, chunk_embeddings, deleted_paths);
+            save(&path, &collection)?;
+            Ok::<_, anyhow::Error>(collection)
+        })
+        .await??;
+        self.collections
+            .write()
+            .unwrap()
+            .insert(repo_id, Arc::new(collection));
+        Ok(())
+    }
+
+    async fn get_relevant_chunks(
+        &self,
+        repository: &Repository,
+        query_embeddings: Embeddings,
+        path: Option<&str>,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Result<Vec<RelevantChunk>> {
+        let collections = self.collections.read().unwrap();
+        let collection = collections
+            .get(&repository.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Repository is not indexed"))?;
+        Ok(collection.search(&query_embeddings, path, files_limit, chunks_limit))
+    }
+
+    async fn get_lexical_chunks(
+        &self,
+        repository: &Repository,
+        query: &str,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Result<Vec<RelevantChunk>> {
+        let collections = self.collections.read().unwrap();
+        let collection = collections
+            .get(&repository.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Repository is not indexed"))?;
+        Ok(collection
+            .lexical
+            .search(&collection.chunks, query, files_limit, chunks_limit))
+    }
+
+    async fn get_file_paths(&self, repository: &Repository) -> Result<RepositoryFilePaths> {
+        let collections = self.collections.read().unwrap();
+        let collection = collections
+            .get(&repository.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Repository is not indexed"))?;
+
+        let mut file_paths: Vec<String> = collection
+            .chunks
+            .iter()
+            .map(|chunk| chunk.path.clone())
+            .collect();
+        file_paths.sort();
+        file_paths.dedup();
+        Ok(RepositoryFilePaths {
+            repo_id: repository.to_string(),
+            file_paths,
+        })
+    }
+
+    async fn is_indexed(&self, repository: &Repository) -> Result<bool> {
+        Ok(self
+            .collections
+            .read()
+            .unwrap()
+            .contains_key(&repository.to_string()))
+    }
+
+    async fn delete_repo(&self, repository: &Repository) -> Result<()> {
+        let repo_id = repository.to_string();
+        let collection = self.collections.write().unwrap().remove(&repo_id);
+        if collection.is_some() {
+            let path = self.collection_path(&repo_id);
+            actix_rt::task::spawn_blocking(move || fs::remove_file(path)).await??;
+        }
+        Ok(())
+    }
+
+    async fn list_repos(&self) -> Result<Vec<RepositoryMetadata>> {
+        let mut repositories: Vec<RepositoryMetadata> = self
+            .collections
+            .read()
+            .unwrap()
+            .values()
+            .map(|collection| collection.metadata.clone())
+            .collect();
+        repositories.sort_by_key(|metadata| metadata.repository().to_string());
+        Ok(repositories)
+    }
+
+    async fn get_repo_metadata(
+        &self,
+        repository: &Repository,
+    ) -> Result<Option<RepositoryMetadata>> {
+        Ok(self
+            .collections
+            .read()
+            .unwrap()
+            .get(&repository.to_string())
+            .map(|collection| collection.metadata.clone()))
+    }
+
+    async fn get_file_hashes(&self, repository: &Repository) -> Result<HashMap<String, String>> {
+        let collections = self.collections.read().unwrap();
+        let hashes: HashMap<String, String> = collections
+            .get(&repository.to_string())
+            .map(|collection| {
+                collection
+                    .chunks
+                    .iter()
+                    .map(|chunk| (chunk.path.clone(), chunk.hash.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(hashes)
+    }
+}
+
+impl EmbeddedDB {
+    pub fn initialize() -> Result<EmbeddedDB> {
+        let mut path =
+            std::env::var("EMBEDDED_DB_PATH").unwrap_or(String::from(EMBEDDED_DB_PATH_DEFAULT));
+
+        if path.is_empty() {
+            path = EMBEDDED_DB_PATH_DEFAULT.to_string();
+        }
+
+        Self::open(PathBuf::from(path))
+    }
+
+    //Loads the collections stored in the directory, which is created if missing
+    //Files of another format version are skipped, their repositories have to be embedded again
+    pub fn open(path: PathBuf) -> Result<EmbeddedDB> {
+        fs::create_dir_all(&path)?;
+
+        let mut collections: HashMap<String, Arc<Collection>> = HashMap::new();
+        for entry in fs::read_dir(&path)? {
+            let file_path = entry?.path();
+            if file_path.extension().and_then(|ext| ext.to_str()) != Some("bin") {
+                continue;
+            }
+            match load(&file_path) {
+                Ok(collection) => {
+                    collections.insert(
+                        collection.metadata.repository().to_string(),
+                        Arc::new(collection),
+                    );
+                }
+                Err(e) => eprintln!("Skipping {}: {}", file_path.display(), e),
+            }
+        }
+
+        Ok(EmbeddedDB {
+            path,
+            collections: RwLock::new(collections),
+        })
+    }
+
+    //Repository IDs may contain characters that aren't valid in file names, e.g. `/` in branch names
+    fn collection_path(&self, repo_id: &str) -> PathBuf {
+        let file_name = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, repo_id.as_bytes());
+        self.path.join(format!("{file_name}.bin"))
+    }
+}
+
+//Writes to a temporary file first, so that a crash can't leave a partially written collection
+//Blocking, it's called from `spawn_blocking`
+fn save(path: &Path, collection: &Collection) -> Result<()> {
+    let temp_path = path.with_extension("tmp");
+
+    let mut writer = BufWriter::new(fs::File::create(&temp_path)?);
+    writer.write_all(&EMBEDDED_DB_FILE_MAGIC)?;
+    writer.write_all(&EMBEDDED_DB_FORMAT_VERSION.to_le_bytes())?;
+    bincode::serialize_into(&mut writer, &(&collection.metadata, &collection.chunks))?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+//Reads a collection written by `save`, the header is checked before the stored types are read
+fn load(path: &Path) -> Result<Collection> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut magic = [0; 8];
+    let mut version = [0; 4];
+    reader.read_exact(&mut magic)?;
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if magic != EMBEDDED_DB_FILE_MAGIC || version != EMBEDDED_DB_FORMAT_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported format, expected version {}",
+            EMBEDDED_DB_FORMAT_VERSION
+        ));
+    }
+    let (metadata, chunks): (RepositoryMetadata, Vec<ChunkEmbeddings>) =
+        bincode::deserialize_from(reader)?;
+    Ok(Collection::new(metadata, chunks))
+}
+
+impl Collection {
+    fn new(metadata: RepositoryMetadata, chunks: Vec<ChunkEmbeddings>) -> Self {
+        Self {
+            metadata,
+            index: ClusterIndex::build(&chunks),
+            lexical: LexicalIndex::build(&chunks),
+            chunks,
+        }
+    }
+
+    fn update(
+        &mut self,
+        metadata: RepositoryMetadata,
+        chunk_embeddings: Vec<ChunkEmbeddings>,
+        deleted_paths: Vec<String>,
+    ) {
+        //The chunks of modified files are replaced as a whole, since their number may have changed
+        let replaced_paths: HashSet<&str> = chunk_embeddings
+            .iter()
+            .map(|chunk| chunk.path.as_str())
+            .chain(deleted_paths.iter().map(String::as_str))
+            .collect();
+        let retained: Vec<bool> = self
+            .chunks
+            .iter()
+            .map(|chunk| !replaced_paths.contains(chunk.path.as_str()))
+            .collect();
+        drop(replaced_paths);
+
+        let mut retained_chunks = retained.iter();
+        self.chunks.retain(|_| *retained_chunks.next().unwrap());
+        if !self.index.is_empty() {
+            let mut retained_assignments = retained.iter();
+            self.index
+                .assignments
+                .retain(|_| *retained_assignments.next().unwrap());
+        }
+        self.lexical.retain(&retained);
+
+        let added_chunks = self.chunks.len();
+        self.chunks.extend(chunk_embeddings);
+        self.metadata = metadata;
+
+        if self.index.needs_rebuild(self.chunks.len()) {
+            self.index = ClusterIndex::build(&self.chunks);
+        } else {
+            self.index.assign(&self.chunks[added_chunks..]);
+        }
+        self.lexical.extend(&self.chunks[added_chunks..]);
+    }
+
+    fn search(
+        &self,
+        query_embeddings: &Embeddings,
+        path: Option<&str>,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Vec<RelevantChunk> {
+        //Searches restricted to a file only score the chunks of the file
+        let probes: Option<Vec<bool>> = match path {
+            Some(_) => None,
+            None => self.index.probes(query_embeddings),
+        };
+
+        let scored_chunks: Vec<(f32, &ChunkEmbeddings)> = self
+            .chunks
+            .par_iter()
+            .enumerate()
+            .filter(|(index, chunk)| match (&probes, path) {
+                (_, Some(path)) => chunk.path == path,
+                (Some(probes), None) => probes[self.index.assignments[*index]],
+                (None, None) => true,
+            })
+            .map(|(_, chunk)| {
+                (
+                    cosine_similarity(query_embeddings, &chunk.embeddings),
+                    chunk,
+                )
+            })
+            .collect();
+        group_chunks(scored_chunks, files_limit, chunks_limit)
+    }
+}
+
+//Inverted file index, to approximate the nearest neighbours of a query
+//The chunks are clustered with k-means and a query only scores the chunks of its nearest clusters
+#[derive(Clone, Default)]
+struct ClusterIndex {
+    centroids: Vec<Embeddings>,
+    //Cluster of each chunk, in the order of the collection's chunks
+    assignments: Vec<usize>,
+    //Number of chunks when the clusters were computed
+    indexed_count: usize,
+}
+
+impl ClusterIndex {
+    fn build(chunks: &[ChunkEmbeddings]) -> Self {
+        if chunks.len() < EMBEDDED_INDEX_MIN_CHUNKS {
+            return Self::default();
+        }
+
+        let clusters = (chunks.len() as f64).sqrt().ceil() as usize;
+        //Evenly spaced chunks as the initial centroids, so that the index is deterministic
+        let mut centroids: Vec<Embeddings> = (0..clusters)
+            .map(|cluster| chunks[cluster * chunks.len() / clusters].embeddings.clone())
+            .collect();
+        for _ in 0..EMBEDDED_INDEX_ITERATIONS {
+            let assignments: Vec<usize> = chunks
+                .par_iter()
+                .map(|chunk| nearest_centroid(&centroids, &chunk.embeddings))
+                .collect();
+
+            let mut sums: Vec<Embeddings> = vec![vec![0.0; centroids[0].len()]; clusters];
+            let mut counts: Vec<usize> = vec![0; clusters];
+            for (chunk, cluster) in chunks.iter().zip(&assignments) {
+                counts[*cluster] += 1;
+                for (sum, value) in sums[*cluster].iter_mut().zip(&chunk.embeddings) {
+                    *sum += value;
+                }
+            }
+            //Empty clusters keep their previous centroid
+            for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+                if count > 0 {
+                    *centroid = sum.into_iter().map(|value| value / count as f32).collect();
+                }
+            }
+        }
+
+        let assignments = chunks
+            .par_iter()
+            .map(|chunk| nearest_centroid(&centroids, &chunk.embeddings))
+            .collect();
+        Self {
+            centroids,
+            assignments,
+            indexed_count: chunks.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.centroids.is_empty()
+    }
+
+    //The clusters drift as chunks are replaced, so they're recomputed once the collection's size has changed significantly
+    fn needs_rebuild(&self, chunks_count: usize) -> bool {
+        if self.is_empty() {
+            chunks_count >= EMBEDDED_INDEX_MIN_CHUNKS
+        } else {
+            chunks_count > self.indexed_count * 2 || chunks_count * 2 < self.indexed_count
+        }
+    }
+
+    //Assigns chunks appended to the collection to their nearest cluster
+    fn assign(&mut self, chunks: &[ChunkEmbeddings]) {
+        if self.is_empty() {
+            return;
+        }
+        let assignments: Vec<usize> = chunks
+            .par_iter()
+            .map(|chunk| nearest_centroid(&self.centroids, &chunk.embeddings))
+            .collect();
+        self.assignments.extend(assignments);
+    }
+
+    //Marks the clusters to search for the query, None if every chunk has to be searched
+    fn probes(&self, query_embeddings: &Embeddings) -> Option<Vec<bool>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut similarities: Vec<(usize, f32)> = self
+            .centroids
+            .par_iter()
+            .map(|centroid| cosine_similarity(query_embeddings, centroid))
+            .enumerate()
+            .collect();
+        similarities.par_sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut probes = vec![false; self.centroids.len()];
+        for (cluster, _) in similarities.into_iter().take(EMBEDDED_INDEX_PROBES) {
+            probes[cluster] = true;
+        }
+        Some(probes)
+    }
+}
+
+fn nearest_centroid(centroids: &[Embeddings], embeddings: &Embeddings) -> usize {
+    centroids
+        .iter()
+        .map(|centroid| cosine_similarity(embeddings, centroid))
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(cluster, _)| cluster)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::fixtures::*;
+
+    //Chunks spread around the unit circle, one file per quadrant
+    fn circle_chunks(count: usize) -> Vec<ChunkEmbeddings> {
+        (0..count)
+            .map(|i| {
+                let angle = i as f32 / count as f32 * std::f32::consts::TAU;
+                let path = format!("{}.rs", i * 4 / count);
+                chunk_embeddings(&path, i, vec![angle.cos(), angle.sin()])
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_collections_persist() {
+        let path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+
+        let db = EmbeddedDB::open(path.clone()).unwrap();
+        db.insert_repo_embeddings(repository_embeddings(
+            vec![
+                chunk_embeddings("a.rs", 1, vec![1.0, 0.0]),
+                chunk_embeddings("b.rs", 1, vec![0.0, 1.0]),
+            ],
+            vec![],
+        ))
+        .await
+        .unwrap();
+        db.update_repo_embeddings(repository_embeddings(vec![], vec!["b.rs".to_string()]))
+            .await
+            .unwrap();
+        drop(db);
+        //Files of another format are skipped
+        fs::write(path.join("stale.bin"), [0; 16]).unwrap();
+
+        let db = EmbeddedDB::open(path.clone()).unwrap();
+        assert_eq!(db.list_repos().await.unwrap().len(), 1);
+        assert!(db.is_indexed(&repository()).await.unwrap());
+        assert_eq!(db.list_repos().await.unwrap()[0].repository(), repository());
+        let chunks = db
+            .get_relevant_chunks(&repository(), vec![0.0, 1.0], None, 2, 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(chunks), vec!["a.rs:1"]);
+        let chunks = db
+            .get_lexical_chunks(&repository(), "b.rs", 2, 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(chunks), vec!["a.rs:1"]);
+
+        db.delete_repo(&repository()).await.unwrap();
+        let db = EmbeddedDB::open(path.clone()).unwrap();
+        assert!(!db.is_indexed(&repository()).await.unwrap());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_save_keeps_collection() {
+        let path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+
+        let db = EmbeddedDB::open(path.clone()).unwrap();
+        db.insert_repo_embeddings(repository_embeddings(
+            vec![chunk_embeddings("a.rs", 1, vec![1.0, 0.0])],
+            vec![],
+        ))
+        .await
+        .unwrap();
+        fs::remove_dir_all(&path).unwrap();
+
+        assert!(db
+            .update_repo_embeddings(repository_embeddings(vec![], vec!["a.rs".to_string()]))
+            .await
+            .is_err());
+        let chunks = db
+            .get_relevant_chunks(&repository(), vec![1.0, 0.0], None, 2, 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(chunks), vec!["a.rs:1"]);
+    }
+
+    #[test]
+    fn test_cluster_index() {
+        let chunks = circle_chunks(EMBEDDED_INDEX_MIN_CHUNKS * 2);
+        let index = ClusterIndex::build(&chunks);
+        assert_eq!(index.assignments.len(), chunks.len());
+        assert!(index.centroids.len() > EMBEDDED_INDEX_PROBES);
+
+        //Only the clusters around the query are searched
+        let probes = index.probes(&vec![1.0, 0.0]).unwrap();
+        let probed_chunks = index
+            .assignments
+            .iter()
+            .filter(|cluster| probes[**cluster])
+            .count();
+        assert!(probed_chunks < chunks.len() / 2);
+
+        let collection = Collection {
+            metadata: repository_embeddings(vec![], vec![]).metadata,
+            lexical: LexicalIndex::build(&chunks),
+            chunks,
+            index,
+        };
+        let relevant_chunks = collection.search(&vec![1.0, 0.0], None, 1, 1);
+        assert_eq!(contents(relevant_chunks), vec!["0.rs:0"]);
+
+        assert!(ClusterIndex::build(&circle_chunks(10)).is_empty());
+    }
+}
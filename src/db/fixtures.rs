@@ -0,0 +1,61 @@
+use crate::{
+    chunker::FileChunk,
+    conversation::RelevantChunk,
+    embeddings::Embeddings,
+    github::{ChunkEmbeddings, Provider, Repository, RepositoryEmbeddings, RepositoryMetadata},
+};
+
+//Repository, chunks and collections shared by the tests of the backends
+//The branch contains a `/`, which isn't valid in file names
+pub fn repository() -> Repository {
+    Repository {
+        owner: "open-sauced".to_string(),
+        name: "ai".to_string(),
+        branch: "feature/embedded".to_string(),
+        provider: Provider::GitHub,
+    }
+}
+
+pub fn chunk_embeddings(path: &str, start_line: usize, embeddings: Embeddings) -> ChunkEmbeddings {
+    ChunkEmbeddings {
+        path: path.to_string(),
+        hash: format!("{path}-hash"),
+        chunk: FileChunk {
+            content: format!("{path}:{start_line}"),
+            start_line,
+            end_line: start_line,
+            symbol: None,
+            headings: Vec::new(),
+        },
+        embeddings,
+    }
+}
+
+pub fn repository_embeddings(
+    chunk_embeddings: Vec<ChunkEmbeddings>,
+    deleted_paths: Vec<String>,
+) -> RepositoryEmbeddings {
+    let repository = repository();
+    RepositoryEmbeddings {
+        repo_id: repository.to_string(),
+        chunk_embeddings,
+        deleted_paths,
+        metadata: RepositoryMetadata {
+            owner: repository.owner,
+            name: repository.name,
+            branch: repository.branch,
+            provider: repository.provider,
+            file_count: 2,
+            indexed_at: 0,
+            embedding_model: "model".to_string(),
+            commit_sha: None,
+            rules: Default::default(),
+            private: false,
+            index: Default::default(),
+        },
+    }
+}
+
+pub fn contents(chunks: Vec<RelevantChunk>) -> Vec<String> {
+    chunks.into_iter().map(|chunk| chunk.content).collect()
+}
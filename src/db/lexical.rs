@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use super::memory::group_chunks;
+use crate::{
+    constants::{BM25_B, BM25_K1, LEXICAL_STOP_WORDS},
+    conversation::RelevantChunk,
+    github::ChunkEmbeddings,
+};
+use rayon::prelude::*;
+
+//BM25 index of the terms of a collection's chunks, built when the chunks are embedded or loaded
+#[derive(Clone, Default)]
+pub(super) struct LexicalIndex {
+    //Terms of each chunk, in the order of the collection's chunks
+    chunks: Vec<ChunkTerms>,
+    //Number of chunks that contain each term
+    document_frequencies: HashMap<String, u32>,
+    //Total number of terms of the chunks, for their average length
+    terms_count: u64,
+}
+
+#[derive(Clone)]
+struct ChunkTerms {
+    frequencies: HashMap<String, u32>,
+    length: u32,
+}
+
+impl LexicalIndex {
+    pub fn build(chunks: &[ChunkEmbeddings]) -> Self {
+        let mut index = Self::default();
+        index.extend(chunks);
+        index
+    }
+
+    //Indexes chunks appended to the collection
+    pub fn extend(&mut self, chunks: &[ChunkEmbeddings]) {
+        let chunk_terms: Vec<ChunkTerms> = chunks
+            .par_iter()
+            .map(|chunk| {
+                let terms = chunk_terms(&chunk.path, &chunk.chunk.content);
+                ChunkTerms {
+                    length: terms.len() as u32,
+                    frequencies: term_frequencies(terms),
+                }
+            })
+            .collect();
+        for terms in &chunk_terms {
+            for term in terms.frequencies.keys() {
+                *self.document_frequencies.entry(term.clone()).or_default() += 1;
+            }
+            self.terms_count += terms.length as u64;
+        }
+        self.chunks.extend(chunk_terms);
+    }
+
+    //Removes the chunks that aren't retained, in the order of the collection's chunks
+    pub fn retain(&mut self, retained: &[bool]) {
+        let mut retained = retained.iter();
+        let document_frequencies = &mut self.document_frequencies;
+        let terms_count = &mut self.terms_count;
+        self.chunks.retain(|terms| {
+            let is_retained = *retained.next().unwrap();
+            if !is_retained {
+                for term in terms.frequencies.keys() {
+                    if let Some(frequency) = document_frequencies.get_mut(term) {
+                        *frequency -= 1;
+                        if *frequency == 0 {
+                            document_frequencies.remove(term);
+                        }
+                    }
+                }
+                *terms_count -= terms.length as u64;
+            }
+            is_retained
+        });
+    }
+
+    //The chunks with any of the query's terms, grouped by file like the vector search's results
+    pub fn search(
+        &self,
+        chunks: &[ChunkEmbeddings],
+        query: &str,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Vec<RelevantChunk> {
+        let chunks_count = self.chunks.len();
+        let average_length = self.terms_count as f32 / chunks_count.max(1) as f32;
+        let query_terms: Vec<(String, f32)> = query_terms(query)
+            .into_iter()
+            .filter_map(|term| {
+                let document_frequency = *self.document_frequencies.get(&term)?;
+                Some((
+                    term,
+                    inverse_document_frequency(chunks_count, document_frequency as usize),
+                ))
+            })
+            .collect();
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        let scored_chunks: Vec<(f32, &ChunkEmbeddings)> = self
+            .chunks
+            .par_iter()
+            .zip(chunks)
+            .map(|(terms, chunk)| {
+                let score: f32 = query_terms
+                    .iter()
+                    .filter_map(|(term, idf)| {
+                        let frequency = *terms.frequencies.get(term)?;
+                        Some(bm25(*idf, frequency, terms.length, average_length))
+                    })
+                    .sum();
+                (score, chunk)
+            })
+            .filter(|(score, _)| *score > 0.0)
+            .collect();
+        group_chunks(scored_chunks, files_limit, chunks_limit)
+    }
+}
+
+//Terms of identifiers and words, lowercased
+//Identifiers are also split into their words, so that `fetch_license_info` and `fetchLicenseInfo` match `license`
+pub(super) fn lexical_terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for identifier in text.split(|c: char| !c.is_alphanumeric() && c != '_') {
+        let words = identifier_words(identifier);
+        if words.len() > 1 {
+            terms.push(identifier.trim_matches('_').to_lowercase());
+        }
+        terms.extend(
+            words
+                .into_iter()
+                .filter(|word| word.chars().count() > 1)
+                .map(str::to_lowercase),
+        );
+    }
+    terms
+}
+
+//The file path is part of a chunk's terms, so that chunks are found by the names of their files
+pub(super) fn chunk_terms(path: &str, content: &str) -> Vec<String> {
+    let mut terms = lexical_terms(path);
+    terms.extend(lexical_terms(content));
+    terms
+}
+
+//Unique terms of a query without its stop words, repeating a term doesn't make it more relevant
+pub(super) fn query_terms(query: &str) -> Vec<String> {
+    let mut terms = lexical_terms(query);
+    terms.retain(|term| !LEXICAL_STOP_WORDS.contains(&term.as_str()));
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+pub(super) fn term_frequencies(terms: Vec<String>) -> HashMap<String, u32> {
+    let mut frequencies: HashMap<String, u32> = HashMap::new();
+    for term in terms {
+        *frequencies.entry(term).or_default() += 1;
+    }
+    frequencies
+}
+
+//The words of snake_case, camelCase and PascalCase identifiers, e.g. `HTTPServer` is `HTTP` and `Server`
+fn identifier_words(identifier: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for part in identifier.split('_').filter(|part| !part.is_empty()) {
+        let chars: Vec<(usize, char)> = part.char_indices().collect();
+        let mut start = 0;
+        for window in 1..chars.len() {
+            let (index, c) = chars[window];
+            let previous = chars[window - 1].1;
+            let next_is_lowercase = chars
+                .get(window + 1)
+                .is_some_and(|(_, next)| next.is_lowercase());
+            let is_boundary = c.is_uppercase()
+                && (previous.is_lowercase()
+                    || previous.is_numeric()
+                    || (previous.is_uppercase() && next_is_lowercase));
+            if is_boundary {
+                words.push(&part[start..index]);
+                start = index;
+            }
+        }
+        words.push(&part[start..]);
+    }
+    words
+}
+
+//Okapi BM25 score of a term that occurs `frequency` times in a chunk of `length` terms
+pub(super) fn bm25(idf: f32, frequency: u32, length: u32, average_length: f32) -> f32 {
+    let frequency = frequency as f32;
+    let normalized_length = length as f32 / average_length.max(1.0);
+    idf * frequency * (BM25_K1 + 1.0)
+        / (frequency + BM25_K1 * (1.0 - BM25_B + BM25_B * normalized_length))
+}
+
+//Rare terms weigh more, the `+ 1` keeps the weight of terms in most chunks positive
+pub(super) fn inverse_document_frequency(chunks_count: usize, document_frequency: usize) -> f32 {
+    let chunks_count = chunks_count as f32;
+    let document_frequency = document_frequency as f32;
+    ((chunks_count - document_frequency + 0.5) / (document_frequency + 0.5) + 1.0).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::FileChunk;
+
+    fn chunk_embeddings(path: &str, content: &str) -> ChunkEmbeddings {
+        ChunkEmbeddings {
+            path: path.to_string(),
+            hash: format!("{path}-hash"),
+            chunk: FileChunk {
+                content: content.to_string(),
+                start_line: 1,
+                end_line: 1,
+                symbol: None,
+                headings: Vec::new(),
+            },
+            embeddings: vec![1.0],
+        }
+    }
+
+    fn paths(chunks: Vec<RelevantChunk>) -> Vec<String> {
+        chunks.into_iter().map(|chunk| chunk.path).collect()
+    }
+
+    #[test]
+    fn test_lexical_terms() {
+        assert_eq!(
+            lexical_terms("pub async fn fetch_license_info(x: &str)"),
+            vec![
+                "pub",
+                "async",
+                "fn",
+                "fetch_license_info",
+                "fetch",
+                "license",
+                "info",
+                "str"
+            ]
+        );
+        assert_eq!(
+            lexical_terms("new HTTPServer(parseJSON2Yaml)"),
+            vec![
+                "new",
+                "httpserver",
+                "http",
+                "server",
+                "parsejson2yaml",
+                "parse",
+                "json2",
+                "yaml"
+            ]
+        );
+        assert_eq!(
+            query_terms("Where is fetch_license_info? fetch it"),
+            vec!["fetch", "fetch_license_info", "info", "license"]
+        );
+    }
+
+    #[test]
+    fn test_lexical_index() {
+        let mut chunks = vec![
+            chunk_embeddings(
+                "src/github/mod.rs",
+                "pub async fn fetch_license_info(repository: &Repository) -> Result<LicenseInfo>",
+            ),
+            chunk_embeddings(
+                "src/routes/mod.rs",
+                "let github_token = github_token(&request);\nlet response = fetch_license_info(&repository).await?;",
+            ),
+            chunk_embeddings(
+                "README.md",
+                "The license of the repository is checked before it's embedded.",
+            ),
+            chunk_embeddings("src/main.rs", "fn main() {}"),
+        ];
+        let mut index = LexicalIndex::build(&chunks);
+
+        let results = index.search(&chunks, "where is fetch_license_info defined?", 10, 1);
+        assert_eq!(
+            paths(results),
+            vec!["src/github/mod.rs", "src/routes/mod.rs", "README.md"]
+        );
+        assert!(index.search(&chunks, "kubernetes", 10, 1).is_empty());
+        //Chunks are found by their file path
+        let results = index.search(&chunks, "main", 10, 1);
+        assert_eq!(paths(results), vec!["src/main.rs"]);
+
+        let retained = [false, true, true, true];
+        let mut retained_chunks = retained.iter();
+        chunks.retain(|_| *retained_chunks.next().unwrap());
+        index.retain(&retained);
+        let added = chunk_embeddings("src/github/license.rs", "pub fn fetch_license_info() {}");
+        index.extend(std::slice::from_ref(&added));
+        chunks.push(added);
+
+        let results = index.search(&chunks, "fetch_license_info", 1, 1);
+        assert_eq!(paths(results), vec!["src/github/license.rs"]);
+        assert_eq!(index.document_frequencies["fetch_license_info"], 2);
+        assert!(!index.document_frequencies.contains_key("result"));
+        assert_eq!(index.terms_count, LexicalIndex::build(&chunks).terms_count);
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use super::{lexical::LexicalIndex, RepositoryEmbeddingsDB};
+use crate::{
+    conversation::RelevantChunk,
+    embeddings::{cosine_similarity, Embeddings},
+    github::{
+        ChunkEmbeddings, Repository, RepositoryEmbeddings, RepositoryFilePaths, RepositoryMetadata,
+    },
+    prelude::*,
+};
+use async_trait::async_trait;
+use rayon::prelude::*;
+
+//In-process store of the embeddings, lost on restart
+//Searches are exhaustive, which is fine for a handful of repositories
+#[derive(Default)]
+pub struct InMemoryDB {
+    collections: RwLock<HashMap<String, Vec<ChunkEmbeddings>>>,
+    repositories: RwLock<HashMap<String, RepositoryMetadata>>,
+    lexical_indexes: RwLock<HashMap<String, LexicalIndex>>,
+}
+
+#[async_trait]
+impl RepositoryEmbeddingsDB for InMemoryDB {
+    async fn insert_repo_embeddings(&self, repo: RepositoryEmbeddings) -> Result<()> {
+        let RepositoryEmbeddings {
+            repo_id,
+            chunk_embeddings,
+            metadata,
+            ..
+        } = repo;
+
+        self.lexical_indexes
+            .write()
+            .unwrap()
+            .insert(repo_id.clone(), LexicalIndex::build(&chunk_embeddings));
+        self.collections
+            .write()
+            .unwrap()
+            .insert(repo_id.clone(), chunk_embeddings);
+        self.repositories.write().unwrap().insert(repo_id, metadata);
+        Ok(())
+    }
+
+    async fn update_repo_embeddings(&self, repo: RepositoryEmbeddings) -> Result<()> {
+        let RepositoryEmbeddings {
+            repo_id,
+            chunk_embeddings,
+            deleted_paths,
+            metadata,
+        } = repo;
+
+        let mut collections = self.collections.write().unwrap();
+        let collection = collections
+            .get_mut(&repo_id)
+            .ok_or_else(|| anyhow::anyhow!("Repository is not indexed"))?;
+
+        //The chunks of modified files are replaced as a whole, since their number may have changed
+        let replaced_paths: HashSet<String> = chunk_embeddings
+            .iter()
+            .map(|chunk| chunk.path.clone())
+            .chain(deleted_paths)
+            .collect();
+        let retained: Vec<bool> = collection
+            .iter()
+            .map(|chunk| !replaced_paths.contains(&chunk.path))
+            .collect();
+        let mut retained_chunks = retained.iter();
+        collection.retain(|_| *retained_chunks.next().unwrap());
+
+        let mut lexical_indexes = self.lexical_indexes.write().unwrap();
+        let lexical_index = lexical_indexes.entry(repo_id.clone()).or_default();
+        lexical_index.retain(&retained);
+        lexical_index.extend(&chunk_embeddings);
+        collection.extend(chunk_embeddings);
+        drop(lexical_indexes);
+        drop(collections);
+
+        self.repositories.write().unwrap().insert(repo_id, metadata);
+        Ok(())
+    }
+
+    async fn get_relevant_chunks(
+        &self,
+        repository: &Repository,
+        query_embeddings: Embeddings,
+        path: Option<&str>,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Result<Vec<RelevantChunk>> {
+        let collections = self.collections.read().unwrap();
+        let collection = collections
+            .get(&repository.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Repository is not indexed"))?;
+
+        let scored_chunks: Vec<(f32, &ChunkEmbeddings)> = collection
+            .par_iter()
+            .filter(|chunk| match path {
+                Some(path) => chunk.path == path,
+                None => true,
+            })
+            .map(|chunk| {
+                (
+                    cosine_similarity(&query_embeddings, &chunk.embeddings),
+                    chunk,
+                )
+            })
+            .collect();
+        Ok(group_chunks(scored_chunks, files_limit, chunks_limit))
+    }
+
+    async fn get_lexical_chunks(
+        &self,
+        repository: &Repository,
+        query: &str,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Result<Vec<RelevantChunk>> {
+        let repo_id = repository.to_string();
+        let collections = self.collections.read().unwrap();
+        let collection = collections
+            .get(&repo_id)
+            .ok_or_else(|| anyhow::anyhow!("Repository is not indexed"))?;
+        let lexical_indexes = self.lexical_indexes.read().unwrap();
+        Ok(lexical_indexes
+            .get(&repo_id)
+            .map(|index| index.search(collection, query, files_limit, chunks_limit))
+            .unwrap_or_default())
+    }
+
+    async fn get_file_paths(&self, repository: &Repository) -> Result<RepositoryFilePaths> {
+        let collections = self.collections.read().unwrap();
+        let collection = collections
+            .get(&repository.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Repository is not indexed"))?;
+
+        let mut file_paths: Vec<String> =
+            collection.iter().map(|chunk| chunk.path.clone()).collect();
+        file_paths.sort();
+        file_paths.dedup();
+        Ok(RepositoryFilePaths {
+            repo_id: repository.to_string(),
+            file_paths,
+        })
+    }
+
+    async fn is_indexed(&self, repository: &Repository) -> Result<bool> {
+        Ok(self
+            .collections
+            .read()
+            .unwrap()
+            .contains_key(&repository.to_string()))
+    }
+
+    async fn delete_repo(&self, repository: &Repository) -> Result<()> {
+        let repo_id = repository.to_string();
+        self.collections.write().unwrap().remove(&repo_id);
+        self.lexical_indexes.write().unwrap().remove(&repo_id);
+        self.repositories.write().unwrap().remove(&repo_id);
+        Ok(())
+    }
+
+    async fn list_repos(&self) -> Result<Vec<RepositoryMetadata>> {
+        let mut repositories: Vec<RepositoryMetadata> = self
+            .repositories
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        repositories.sort_by_key(|metadata| metadata.repository().to_string());
+        Ok(repositories)
+    }
+
+    async fn get_repo_metadata(
+        &self,
+        repository: &Repository,
+    ) -> Result<Option<RepositoryMetadata>> {
+        Ok(self
+            .repositories
+            .read()
+            .unwrap()
+            .get(&repository.to_string())
+            .cloned())
+    }
+
+    async fn get_file_hashes(&self, repository: &Repository) -> Result<HashMap<String, String>> {
+        let collections = self.collections.read().unwrap();
+        let hashes: HashMap<String, String> = collections
+            .get(&repository.to_string())
+            .map(|collection| {
+                collection
+                    .iter()
+                    .map(|chunk| (chunk.path.clone(), chunk.hash.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(hashes)
+    }
+}
+
+//Groups the chunks by file path, like Qdrant's search groups
+//Files are ordered by their highest scored chunk
+pub(super) fn group_chunks(
+    mut scored_chunks: Vec<(f32, &ChunkEmbeddings)>,
+    files_limit: usize,
+    chunks_limit: usize,
+) -> Vec<RelevantChunk> {
+    scored_chunks.par_sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut groups: Vec<(&str, Vec<RelevantChunk>)> = Vec::new();
+    for (_, chunk) in scored_chunks {
+        match groups.iter().position(|(path, _)| *path == chunk.path) {
+            Some(index) if groups[index].1.len() < chunks_limit => {
+                groups[index].1.push(chunk.into())
+            }
+            None if groups.len() < files_limit && chunks_limit > 0 => {
+                groups.push((&chunk.path, vec![chunk.into()]))
+            }
+            _ => {}
+        }
+    }
+    groups.into_iter().flat_map(|(_, chunks)| chunks).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::fixtures::*;
+
+    #[tokio::test]
+    async fn test_get_relevant_chunks() {
+        let db = InMemoryDB::default();
+        db.insert_repo_embeddings(repository_embeddings(
+            vec![
+                chunk_embeddings("a.rs", 1, vec![1.0, 0.0]),
+                chunk_embeddings("a.rs", 2, vec![0.8, 0.2]),
+                chunk_embeddings("a.rs", 3, vec![0.7, 0.3]),
+                chunk_embeddings("b.rs", 1, vec![0.9, 0.1]),
+                chunk_embeddings("c.rs", 1, vec![0.0, 1.0]),
+            ],
+            vec![],
+        ))
+        .await
+        .unwrap();
+
+        let chunks = db
+            .get_relevant_chunks(&repository(), vec![1.0, 0.0], None, 2, 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(chunks), vec!["a.rs:1", "a.rs:2", "b.rs:1"]);
+
+        let chunks = db
+            .get_relevant_chunks(&repository(), vec![1.0, 0.0], Some("c.rs"), 1, 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(chunks), vec!["c.rs:1"]);
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete_repo() {
+        let db = InMemoryDB::default();
+        assert!(!db.is_indexed(&repository()).await.unwrap());
+
+        db.insert_repo_embeddings(repository_embeddings(
+            vec![
+                chunk_embeddings("a.rs", 1, vec![1.0]),
+                chunk_embeddings("a.rs", 2, vec![1.0]),
+                chunk_embeddings("b.rs", 1, vec![1.0]),
+            ],
+            vec![],
+        ))
+        .await
+        .unwrap();
+        db.update_repo_embeddings(repository_embeddings(
+            vec![chunk_embeddings("a.rs", 5, vec![1.0])],
+            vec!["b.rs".to_string()],
+        ))
+        .await
+        .unwrap();
+
+        let chunks = db
+            .get_relevant_chunks(&repository(), vec![1.0], None, 10, 10)
+            .await
+            .unwrap();
+        assert_eq!(contents(chunks), vec!["a.rs:5"]);
+        let chunks = db
+            .get_lexical_chunks(&repository(), "a.rs b.rs", 10, 10)
+            .await
+            .unwrap();
+        assert_eq!(contents(chunks), vec!["a.rs:5"]);
+        assert_eq!(
+            db.get_file_hashes(&repository()).await.unwrap(),
+            HashMap::from([("a.rs".to_string(), "a.rs-hash".to_string())])
+        );
+        assert_eq!(db.list_repos().await.unwrap().len(), 1);
+
+        db.delete_repo(&repository()).await.unwrap();
+        assert!(!db.is_indexed(&repository()).await.unwrap());
+        assert!(db.get_repo_metadata(&repository()).await.unwrap().is_none());
+    }
+}
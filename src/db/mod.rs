@@ -1,23 +1,74 @@
+use crate::conversation::RelevantChunk;
 use crate::embeddings::Embeddings;
-use crate::github::{Repository, RepositoryEmbeddings, RepositoryFilePaths};
+use crate::github::{Repository, RepositoryEmbeddings, RepositoryFilePaths, RepositoryMetadata};
 use crate::prelude::*;
+mod embedded;
+#[cfg(test)]
+mod fixtures;
+mod lexical;
+mod memory;
 mod qdrant;
 use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
 
+pub use embedded::*;
+pub use memory::*;
 pub use qdrant::*;
 
+pub type DynRepositoryEmbeddingsDB = dyn RepositoryEmbeddingsDB + Send + Sync;
+
 #[async_trait]
 pub trait RepositoryEmbeddingsDB {
     async fn insert_repo_embeddings(&self, repo: RepositoryEmbeddings) -> Result<()>;
 
-    async fn get_relevant_files(
+    //Applies the changes of an incremental re-index to an existing collection
+    async fn update_repo_embeddings(&self, repo: RepositoryEmbeddings) -> Result<()>;
+
+    //Returns the most similar chunks of up to `files_limit` files, optionally restricted to `path`
+    async fn get_relevant_chunks(
         &self,
         repository: &Repository,
         query_embeddings: Embeddings,
-        limit: usize,
-    ) -> Result<RepositoryFilePaths>;
+        path: Option<&str>,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Result<Vec<RelevantChunk>>;
+
+    //Returns the chunks that best match the terms of the query by BM25, of up to `files_limit` files
+    async fn get_lexical_chunks(
+        &self,
+        repository: &Repository,
+        query: &str,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Result<Vec<RelevantChunk>>;
 
     async fn get_file_paths(&self, repository: &Repository) -> Result<RepositoryFilePaths>;
 
     async fn is_indexed(&self, repository: &Repository) -> Result<bool>;
+
+    async fn delete_repo(&self, repository: &Repository) -> Result<()>;
+
+    async fn list_repos(&self) -> Result<Vec<RepositoryMetadata>>;
+
+    async fn get_repo_metadata(
+        &self,
+        repository: &Repository,
+    ) -> Result<Option<RepositoryMetadata>>;
+
+    //Maps the path of every indexed file to the hash of its content
+    //Empty if the hashes of the collection are unavailable
+    async fn get_file_hashes(&self, repository: &Repository) -> Result<HashMap<String, String>>;
+}
+
+//Creates the backend selected by the `VECTOR_DB` env var
+pub fn initialize() -> Result<Arc<DynRepositoryEmbeddingsDB>> {
+    let vector_db = std::env::var("VECTOR_DB").unwrap_or_default();
+    match vector_db.as_str() {
+        "" | "qdrant" => Ok(Arc::new(QdrantDB::initialize()?)),
+        "memory" => Ok(Arc::new(InMemoryDB::default())),
+        "embedded" => Ok(Arc::new(EmbeddedDB::initialize()?)),
+        _ => Err(anyhow::anyhow!("Invalid VECTOR_DB: {}", vector_db)),
+    }
 }
@@ -1,19 +1,33 @@
 use std::collections::HashMap;
 
-use super::RepositoryEmbeddingsDB;
+use super::{
+    lexical::{bm25, chunk_terms, inverse_document_frequency, query_terms, term_frequencies},
+    RepositoryEmbeddingsDB,
+};
 use crate::{
-    constants::{EMBEDDINGS_DIMENSION, MAX_FILES_COUNT, QDRANT_URL_DEFAULT},
+    constants::{
+        BM25_AVERAGE_CHUNK_TERMS, EMBEDDINGS_DIMENSION, MAX_FILES_COUNT, QDRANT_URL_DEFAULT,
+        REPOSITORIES_COLLECTION_NAME,
+    },
+    conversation::RelevantChunk,
     embeddings::Embeddings,
-    github::{FileEmbeddings, Repository, RepositoryEmbeddings, RepositoryFilePaths},
+    github::{
+        ChunkEmbeddings, Repository, RepositoryEmbeddings, RepositoryFilePaths, RepositoryMetadata,
+    },
     prelude::*,
 };
 use anyhow::Ok;
 use async_trait::async_trait;
 use qdrant_client::{
     prelude::*,
-    qdrant::{vectors_config::Config, ScrollPoints, VectorParams, VectorsConfig},
+    qdrant::{
+        vectors_config::Config, Condition, CountPoints, FieldType, Filter, PointId, RetrievedPoint,
+        ScrollPoints, SearchGroupsResponse, SearchPointGroups, SparseIndices, SparseVectorConfig,
+        SparseVectorParams, Vector, VectorParams, VectorsConfig, WithPayloadSelector,
+    },
 };
 use rayon::prelude::*;
+use sha1::{Digest, Sha1};
 
 pub struct QdrantDB {
     client: QdrantClient,
@@ -22,12 +36,19 @@ pub struct QdrantDB {
 #[async_trait]
 impl RepositoryEmbeddingsDB for QdrantDB {
     async fn insert_repo_embeddings(&self, repo: RepositoryEmbeddings) -> Result<()> {
-        if self.client.collection_exists(&repo.repo_id).await? {
-            self.client.delete_collection(&repo.repo_id).await?;
+        let RepositoryEmbeddings {
+            repo_id,
+            chunk_embeddings,
+            metadata,
+            ..
+        } = repo;
+
+        if self.client.collection_exists(&repo_id).await? {
+            self.client.delete_collection(&repo_id).await?;
         }
         self.client
             .create_collection(&CreateCollection {
-                collection_name: repo.repo_id.clone(),
+                collection_name: repo_id.clone(),
                 vectors_config: Some(VectorsConfig {
                     config: Some(Config::Params(VectorParams {
                         size: EMBEDDINGS_DIMENSION as u64,
@@ -35,70 +56,165 @@ impl RepositoryEmbeddingsDB for QdrantDB {
                         ..Default::default()
                     })),
                 }),
+                //The BM25 weights of the chunks' terms, by `term_index`
+                sparse_vectors_config: Some(SparseVectorConfig {
+                    map: HashMap::from([("terms".to_string(), SparseVectorParams::default())]),
+                }),
                 ..Default::default()
             })
             .await?;
+        //Chunks are grouped and filtered by their file path
+        self.client
+            .create_field_index(&repo_id, "path", FieldType::Keyword, None, None)
+            .await?;
+        //Document frequencies are counted by the unique terms of the chunks
+        self.client
+            .create_field_index(&repo_id, "terms", FieldType::Keyword, None, None)
+            .await?;
 
-        let points: Vec<PointStruct> = repo
-            .file_embeddings
-            .into_par_iter()
-            .enumerate()
-            .map(|file| {
-                let FileEmbeddings { path, embeddings } = file.1;
-                let payload: Payload = HashMap::from([("path", path.into())]).into();
+        if !chunk_embeddings.is_empty() {
+            self.client
+                .upsert_points(&repo_id, None, chunk_points(chunk_embeddings), None)
+                .await?;
+        }
 
-                PointStruct::new(file.0 as u64, embeddings, payload)
-            })
+        self.insert_repo_metadata(&repo_id, metadata).await
+    }
+
+    async fn update_repo_embeddings(&self, repo: RepositoryEmbeddings) -> Result<()> {
+        let RepositoryEmbeddings {
+            repo_id,
+            chunk_embeddings,
+            deleted_paths,
+            metadata,
+        } = repo;
+
+        //The chunks of modified files are replaced as a whole, since their number may have changed
+        let mut replaced_paths: Vec<String> = chunk_embeddings
+            .iter()
+            .map(|chunk| chunk.path.clone())
+            .chain(deleted_paths)
             .collect();
-        self.client
-            .upsert_points(repo.repo_id, None, points, None)
-            .await?;
-        Ok(())
+        replaced_paths.sort();
+        replaced_paths.dedup();
+        if !replaced_paths.is_empty() {
+            let filter = Filter::must([Condition::matches("path", replaced_paths)]);
+            self.client
+                .delete_points(&repo_id, None, &filter.into(), None)
+                .await?;
+        }
+        if !chunk_embeddings.is_empty() {
+            self.client
+                .upsert_points(&repo_id, None, chunk_points(chunk_embeddings), None)
+                .await?;
+        }
+
+        self.insert_repo_metadata(&repo_id, metadata).await
     }
 
-    async fn get_relevant_files(
+    async fn get_relevant_chunks(
         &self,
         repository: &Repository,
         query_embeddings: Embeddings,
-        limit: usize,
-    ) -> Result<RepositoryFilePaths> {
+        path: Option<&str>,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Result<Vec<RelevantChunk>> {
+        let filter = path.map(|path| Filter::must([Condition::matches("path", path.to_string())]));
         let search_response = self
             .client
-            .search_points(&SearchPoints {
+            .search_groups(&SearchPointGroups {
                 collection_name: repository.to_string(),
                 vector: query_embeddings,
-                with_payload: Some(true.into()),
-                limit: limit as u64,
+                filter,
+                limit: files_limit as u32,
+                with_payload: Some(chunk_payload_selector()),
+                group_by: "path".to_string(),
+                group_size: chunks_limit as u32,
                 ..Default::default()
             })
             .await?;
-        let paths: Vec<String> = search_response
-            .result
-            .into_iter()
-            .map(|point| point.payload["path"].to_string().replace('\"', ""))
-            .collect();
-        Ok(RepositoryFilePaths {
-            repo_id: repository.to_string(),
-            file_paths: paths,
-        })
+        Ok(search_response_to_chunks(search_response))
     }
 
-    async fn get_file_paths(&self, repository: &Repository) -> Result<RepositoryFilePaths> {
-        let scroll_reponse = self
+    //Chunks are scored by the dot product of their `terms` sparse vector, which holds the BM25 weight of each term,
+    //and of the query's, which holds the IDF of each term
+    //Document frequencies are the estimates of the `terms` payload index, so that they don't need a full scan
+    async fn get_lexical_chunks(
+        &self,
+        repository: &Repository,
+        query: &str,
+        files_limit: usize,
+        chunks_limit: usize,
+    ) -> Result<Vec<RelevantChunk>> {
+        let query_terms = query_terms(query);
+        if query_terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let chunks_count = self.count_points(repository, None).await?;
+        let mut query_weights: HashMap<u32, f32> = HashMap::new();
+        for term in query_terms {
+            let filter = Filter::must([Condition::matches("terms", term.clone())]);
+            let document_frequency = self.count_points(repository, Some(filter)).await?;
+            if document_frequency > 0 {
+                *query_weights.entry(term_index(&term)).or_default() +=
+                    inverse_document_frequency(chunks_count, document_frequency);
+            }
+        }
+        if query_weights.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (indices, weights): (Vec<u32>, Vec<f32>) = query_weights.into_iter().unzip();
+        let search_response = self
             .client
-            .scroll(&ScrollPoints {
+            .search_groups(&SearchPointGroups {
                 collection_name: repository.to_string(),
-                limit: Some(MAX_FILES_COUNT as u32),
-                with_payload: Some(true.into()),
+                vector: weights,
+                sparse_indices: Some(SparseIndices { data: indices }),
+                vector_name: Some("terms".to_string()),
+                limit: files_limit as u32,
+                with_payload: Some(chunk_payload_selector()),
+                group_by: "path".to_string(),
+                group_size: chunks_limit as u32,
                 ..Default::default()
             })
             .await?;
+        Ok(search_response_to_chunks(search_response))
+    }
 
-        let file_paths: Vec<String> = scroll_reponse
-            .result
-            .par_iter()
-            .map(|point| point.payload["path"].to_string().replace('\"', ""))
-            .collect();
+    async fn get_file_paths(&self, repository: &Repository) -> Result<RepositoryFilePaths> {
+        let mut file_paths: Vec<String> = Vec::new();
+        let mut offset: Option<PointId> = None;
+        loop {
+            let scroll_reponse = self
+                .client
+                .scroll(&ScrollPoints {
+                    collection_name: repository.to_string(),
+                    offset,
+                    limit: Some(MAX_FILES_COUNT as u32),
+                    with_payload: Some(vec!["path"].into()),
+                    ..Default::default()
+                })
+                .await?;
+
+            file_paths.extend(
+                scroll_reponse
+                    .result
+                    .into_iter()
+                    .filter_map(|point| payload_str(&point.payload, "path")),
+            );
+
+            offset = scroll_reponse.next_page_offset;
+            if offset.is_none() {
+                break;
+            }
+        }
+
+        //Every chunk of a file holds its path
+        file_paths.sort();
+        file_paths.dedup();
         Ok(RepositoryFilePaths {
             repo_id: repository.to_string(),
             file_paths,
@@ -108,9 +224,196 @@ impl RepositoryEmbeddingsDB for QdrantDB {
     async fn is_indexed(&self, repository: &Repository) -> Result<bool> {
         self.client.collection_exists(repository.to_string()).await
     }
+
+    async fn delete_repo(&self, repository: &Repository) -> Result<()> {
+        let repo_id = repository.to_string();
+        self.client.delete_collection(&repo_id).await?;
+
+        if self
+            .client
+            .collection_exists(REPOSITORIES_COLLECTION_NAME)
+            .await?
+        {
+            let ids: Vec<PointId> = vec![metadata_point_id(&repo_id).into()];
+            self.client
+                .delete_points(REPOSITORIES_COLLECTION_NAME, None, &ids.into(), None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn list_repos(&self) -> Result<Vec<RepositoryMetadata>> {
+        if !self
+            .client
+            .collection_exists(REPOSITORIES_COLLECTION_NAME)
+            .await?
+        {
+            return Ok(Vec::new());
+        }
+
+        let mut points: Vec<RetrievedPoint> = Vec::new();
+        let mut offset: Option<PointId> = None;
+        loop {
+            let scroll_reponse = self
+                .client
+                .scroll(&ScrollPoints {
+                    collection_name: REPOSITORIES_COLLECTION_NAME.to_string(),
+                    offset,
+                    limit: Some(MAX_FILES_COUNT as u32),
+                    with_payload: Some(true.into()),
+                    ..Default::default()
+                })
+                .await?;
+
+            points.extend(scroll_reponse.result);
+            offset = scroll_reponse.next_page_offset;
+            if offset.is_none() {
+                break;
+            }
+        }
+
+        //Skip stale entries of collections that were removed outside of the service
+        let collections: Vec<String> = self
+            .client
+            .list_collections()
+            .await?
+            .collections
+            .into_iter()
+            .map(|collection| collection.name)
+            .collect();
+
+        let repositories: Vec<RepositoryMetadata> = points
+            .into_iter()
+            .filter_map(|point| payload_to_metadata(point.payload))
+            .filter(|metadata: &RepositoryMetadata| {
+                collections.contains(&metadata.repository().to_string())
+            })
+            .collect();
+        Ok(repositories)
+    }
+
+    async fn get_repo_metadata(
+        &self,
+        repository: &Repository,
+    ) -> Result<Option<RepositoryMetadata>> {
+        if !self
+            .client
+            .collection_exists(REPOSITORIES_COLLECTION_NAME)
+            .await?
+        {
+            return Ok(None);
+        }
+
+        let ids: Vec<PointId> = vec![metadata_point_id(&repository.to_string()).into()];
+        let response = self
+            .client
+            .get_points(
+                REPOSITORIES_COLLECTION_NAME,
+                None,
+                &ids,
+                Some(false),
+                Some(true),
+                None,
+            )
+            .await?;
+        Ok(response
+            .result
+            .into_iter()
+            .next()
+            .and_then(|point| payload_to_metadata(point.payload)))
+    }
+
+    async fn get_file_hashes(&self, repository: &Repository) -> Result<HashMap<String, String>> {
+        if !self.is_indexed(repository).await? {
+            return Ok(HashMap::new());
+        }
+
+        let mut hashes: HashMap<String, String> = HashMap::new();
+        let mut offset: Option<PointId> = None;
+        loop {
+            let scroll_reponse = self
+                .client
+                .scroll(&ScrollPoints {
+                    collection_name: repository.to_string(),
+                    offset,
+                    limit: Some(MAX_FILES_COUNT as u32),
+                    with_payload: Some(vec!["path", "hash", "start_line"].into()),
+                    ..Default::default()
+                })
+                .await?;
+
+            for point in scroll_reponse.result {
+                let payload = &point.payload;
+                match (payload_str(payload, "path"), payload_str(payload, "hash")) {
+                    (Some(path), Some(hash)) if payload.contains_key("start_line") => {
+                        hashes.insert(path, hash);
+                    }
+                    //Indexed before file hashes or chunks were stored, the collection has to be re-created
+                    _ => return Ok(HashMap::new()),
+                }
+            }
+
+            offset = scroll_reponse.next_page_offset;
+            if offset.is_none() {
+                return Ok(hashes);
+            }
+        }
+    }
 }
 
 impl QdrantDB {
+    //Estimated from the payload indexes for filtered counts, exact counts would scan the collection
+    async fn count_points(&self, repository: &Repository, filter: Option<Filter>) -> Result<usize> {
+        let count_response = self
+            .client
+            .count(&CountPoints {
+                collection_name: repository.to_string(),
+                filter,
+                exact: Some(false),
+                ..Default::default()
+            })
+            .await?;
+        Ok(count_response
+            .result
+            .map(|result| result.count as usize)
+            .unwrap_or_default())
+    }
+
+    async fn insert_repo_metadata(
+        &self,
+        repo_id: &str,
+        metadata: RepositoryMetadata,
+    ) -> Result<()> {
+        if !self
+            .client
+            .collection_exists(REPOSITORIES_COLLECTION_NAME)
+            .await?
+        {
+            //Points in the collection only hold a payload, the vector is a placeholder
+            self.client
+                .create_collection(&CreateCollection {
+                    collection_name: REPOSITORIES_COLLECTION_NAME.to_string(),
+                    vectors_config: Some(VectorsConfig {
+                        config: Some(Config::Params(VectorParams {
+                            size: 1,
+                            distance: Distance::Dot.into(),
+                            ..Default::default()
+                        })),
+                    }),
+                    ..Default::default()
+                })
+                .await?;
+        }
+
+        let payload = Payload::try_from(serde_json::to_value(metadata)?)
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let point = PointStruct::new(metadata_point_id(repo_id), vec![1.0], payload);
+        self.client
+            .upsert_points(REPOSITORIES_COLLECTION_NAME, None, vec![point], None)
+            .await?;
+        Ok(())
+    }
+
     pub fn initialize() -> Result<QdrantDB> {
         let mut qdrant_url =
             std::env::var("QDRANT_URL").unwrap_or(String::from(QDRANT_URL_DEFAULT));
@@ -124,3 +427,119 @@ impl QdrantDB {
         Ok(QdrantDB { client })
     }
 }
+
+//Deterministic ID of a repository's point in the repositories collection
+fn metadata_point_id(repo_id: &str) -> String {
+    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, repo_id.as_bytes()).to_string()
+}
+
+fn chunk_points(chunk_embeddings: Vec<ChunkEmbeddings>) -> Vec<PointStruct> {
+    chunk_embeddings
+        .into_par_iter()
+        .map(|chunk_embeddings| {
+            let ChunkEmbeddings {
+                path,
+                hash,
+                chunk,
+                embeddings,
+            } = chunk_embeddings;
+            //Chunks are always replaced by file path, so their IDs don't have to be deterministic
+            let id = uuid::Uuid::new_v4().to_string();
+            let terms = chunk_terms(&path, &chunk.content);
+            let length = terms.len() as u32;
+            let mut term_weights: HashMap<u32, f32> = HashMap::new();
+            let mut unique_terms: Vec<String> = Vec::new();
+            for (term, frequency) in term_frequencies(terms) {
+                //The chunks' average length isn't known as they're written, so it's approximated
+                *term_weights.entry(term_index(&term)).or_default() +=
+                    bm25(1.0, frequency, length, BM25_AVERAGE_CHUNK_TERMS);
+                unique_terms.push(term);
+            }
+            let term_weights: Vec<(u32, f32)> = term_weights.into_iter().collect();
+            let mut payload = HashMap::from([
+                ("path", path.into()),
+                ("hash", hash.into()),
+                ("content", chunk.content.into()),
+                ("start_line", (chunk.start_line as i64).into()),
+                ("end_line", (chunk.end_line as i64).into()),
+                ("terms", unique_terms.into()),
+            ]);
+            if let Some(symbol) = chunk.symbol {
+                payload.insert("symbol", symbol.into());
+            }
+            if !chunk.headings.is_empty() {
+                payload.insert("headings", chunk.headings.into());
+            }
+            let payload: Payload = payload.into();
+
+            let vectors: HashMap<String, Vector> = HashMap::from([
+                //The unnamed dense vector
+                (String::new(), embeddings.into()),
+                ("terms".to_string(), term_weights.into()),
+            ]);
+            PointStruct::new(id, vectors, payload)
+        })
+        .collect()
+}
+
+//Index of a term in the `terms` sparse vectors
+fn term_index(term: &str) -> u32 {
+    let digest = Sha1::digest(term.as_bytes());
+    u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+//Only the payload of the chunk, without its terms
+fn chunk_payload_selector() -> WithPayloadSelector {
+    vec![
+        "path",
+        "content",
+        "start_line",
+        "end_line",
+        "symbol",
+        "headings",
+    ]
+    .into()
+}
+
+fn search_response_to_chunks(search_response: SearchGroupsResponse) -> Vec<RelevantChunk> {
+    search_response
+        .result
+        .map(|result| result.groups)
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|group| group.hits)
+        .filter_map(|point| payload_to_chunk(point.payload))
+        .collect()
+}
+
+fn payload_to_chunk(payload: HashMap<String, Value>) -> Option<RelevantChunk> {
+    Some(RelevantChunk {
+        path: payload_str(&payload, "path")?,
+        content: payload_str(&payload, "content")?,
+        start_line: payload.get("start_line")?.as_integer()? as usize,
+        end_line: payload.get("end_line")?.as_integer()? as usize,
+        symbol: payload_str(&payload, "symbol"),
+        headings: payload
+            .get("headings")
+            .and_then(|headings| headings.as_list())
+            .map(|headings| {
+                headings
+                    .iter()
+                    .filter_map(|heading| heading.as_str().cloned())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+fn payload_str(payload: &HashMap<String, Value>, key: &str) -> Option<String> {
+    payload.get(key)?.as_str().cloned()
+}
+
+fn payload_to_metadata(payload: HashMap<String, Value>) -> Option<RepositoryMetadata> {
+    let payload: serde_json::Map<String, serde_json::Value> = payload
+        .into_iter()
+        .map(|(key, value)| (key, value.into_json()))
+        .collect();
+    serde_json::from_value(payload.into()).ok()
+}
@@ -1,23 +1,76 @@
-use crate::prelude::*;
+use crate::{
+    chunker::ChunkSizer,
+    constants::{
+        CHUNK_MAX_TOKENS_DEFAULT, CHUNK_OVERLAP_TOKENS_DEFAULT, EMBEDDINGS_MAX_SEQUENCE_LENGTH,
+    },
+    prelude::*,
+};
 use fastembed::{InitOptions, TextEmbedding};
+use hf_hub::{api::sync::ApiBuilder, Cache};
+use tokenizers::Tokenizer;
 
 use super::{Embeddings, EmbeddingsModel};
 
 pub struct Fastembed {
     model: TextEmbedding,
+    chunk_sizer: ChunkSizer,
 }
 
 impl Fastembed {
     pub fn try_new() -> Result<Self> {
-        let model = TextEmbedding::try_new(InitOptions {
+        let options = InitOptions {
             model_name: fastembed::EmbeddingModel::AllMiniLML6V2,
             ..Default::default()
-        })?;
-        Ok(Self { model })
+        };
+        let model = TextEmbedding::try_new(options.clone())?;
+        Ok(Self {
+            model,
+            chunk_sizer: Self::chunk_sizer(&options)?,
+        })
+    }
+
+    //Chunks are sized with the model's tokenizer, fastembed truncates texts longer than its `max_length`
+    //`CHUNK_MAX_TOKENS` is capped to the model's max sequence length, leaving room for the special tokens that the tokenizer adds to every text
+    fn chunk_sizer(options: &InitOptions) -> Result<ChunkSizer> {
+        //Read from the cache that fastembed downloaded the model to
+        let tokenizer_path = ApiBuilder::from_cache(Cache::new(options.cache_dir.clone()))
+            .with_progress(false)
+            .build()?
+            .model(options.model_name.to_string())
+            .get("tokenizer.json")?;
+        let mut tokenizer = Tokenizer::from_file(tokenizer_path).map_err(anyhow::Error::msg)?;
+        tokenizer.with_padding(None);
+        tokenizer
+            .with_truncation(None)
+            .map_err(anyhow::Error::msg)?;
+
+        let special_tokens = tokenizer
+            .encode("", true)
+            .map_err(anyhow::Error::msg)?
+            .len();
+        let max_tokens = env_usize("CHUNK_MAX_TOKENS", CHUNK_MAX_TOKENS_DEFAULT)
+            .min(EMBEDDINGS_MAX_SEQUENCE_LENGTH.min(options.max_length) - special_tokens);
+        let overlap_tokens = env_usize("CHUNK_OVERLAP_TOKENS", CHUNK_OVERLAP_TOKENS_DEFAULT);
+        Ok(ChunkSizer::tokens(tokenizer, max_tokens, overlap_tokens))
+    }
+}
+
+fn env_usize(key: &str, default: usize) -> usize {
+    match std::env::var(key).map(|value| value.parse()) {
+        Ok(Ok(value)) => value,
+        _ => default,
     }
 }
 
 impl EmbeddingsModel for Fastembed {
+    fn model_name(&self) -> &str {
+        "sentence-transformers/all-MiniLM-L6-v2"
+    }
+
+    fn chunk_sizer(&self) -> &ChunkSizer {
+        &self.chunk_sizer
+    }
+
     fn embed<S: AsRef<str> + Send + Sync>(&self, texts: Vec<S>) -> Result<Vec<Embeddings>> {
         self.model.embed(texts, None)
     }
@@ -1,11 +1,14 @@
 mod fastembed;
 
-use crate::prelude::*;
+use crate::{chunker::ChunkSizer, prelude::*};
 
 pub use fastembed::*;
 pub type Embeddings = Vec<f32>;
 
 pub trait EmbeddingsModel {
+    fn model_name(&self) -> &str;
+    //Sizes chunks so that they're embedded without being truncated
+    fn chunk_sizer(&self) -> &ChunkSizer;
     fn embed<S: AsRef<str> + Send + Sync>(&self, texts: Vec<S>) -> Result<Vec<Embeddings>>;
     fn query_embed<S: AsRef<str> + Send + Sync>(&self, query: S) -> Result<Embeddings>;
 }
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sha1::{Digest, Sha1};
+
+use super::Repository;
+use crate::constants::ACCESS_CACHE_TTL_SECS;
+
+//Whether tokens can read the repositories of token-scoped collections, so that they aren't checked on every request
+//Results expire after `ttl`, so that granted or revoked access is noticed
+pub struct AccessCache {
+    //Keyed by the digest of the token and the repository ID
+    entries: Mutex<HashMap<(String, String), (bool, Instant)>>,
+    ttl: Duration,
+}
+
+impl Default for AccessCache {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(ACCESS_CACHE_TTL_SECS))
+    }
+}
+
+impl AccessCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    //Whether the repository can be read with the token, used to authorize access to token-scoped collections
+    //Failed checks aren't cached, so that they're retried by the next request
+    pub async fn can_read(&self, repository: &Repository, github_token: &str) -> bool {
+        let key = (token_digest(github_token), repository.to_string());
+        if let Some(can_read) = self.get(&key) {
+            return can_read;
+        }
+
+        let source = repository.provider.source(Some(github_token));
+        match source.can_read(repository).await {
+            Ok(can_read) => {
+                self.insert(key, can_read);
+                can_read
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn get(&self, key: &(String, String)) -> Option<bool> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, checked_at)| checked_at.elapsed() < self.ttl);
+        entries.get(key).map(|(can_read, _)| *can_read)
+    }
+
+    fn insert(&self, key: (String, String), can_read: bool) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, (can_read, Instant::now()));
+    }
+}
+
+//Tokens aren't kept in memory longer than the requests that sent them
+fn token_digest(github_token: &str) -> String {
+    Sha1::digest(github_token)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_cache_ttl() {
+        let key = (token_digest("token"), "o-n-b".to_string());
+
+        let cache = AccessCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get(&key), None);
+        cache.insert(key.clone(), true);
+        assert_eq!(cache.get(&key), Some(true));
+        assert_eq!(cache.get(&(token_digest("other"), key.1.clone())), None);
+
+        let cache = AccessCache::new(Duration::ZERO);
+        cache.insert(key.clone(), true);
+        assert_eq!(cache.get(&key), None);
+    }
+}
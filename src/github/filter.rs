@@ -0,0 +1,243 @@
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::{GitRules, LinguistAttribute, LinguistState, IGNORED_DIRECTORIES, IGNORED_EXTENSIONS};
+use crate::prelude::*;
+
+//The globs of the files to index, as requested to `/embed`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathRules {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    //Whether files matched by the repository's `.gitignore` files are skipped
+    #[serde(default = "default_gitignore")]
+    pub gitignore: bool,
+    //Files that set or unset any of these linguist attributes in the repository's `.gitattributes` files are skipped
+    #[serde(default = "LinguistState::excluded_by_default")]
+    pub linguist: Vec<LinguistState>,
+}
+
+impl Default for PathRules {
+    fn default() -> Self {
+        PathRules {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            gitignore: default_gitignore(),
+            linguist: LinguistState::excluded_by_default(),
+        }
+    }
+}
+
+impl PathRules {
+    pub fn filter(&self) -> Result<PathFilter> {
+        let mut filter = PathFilter::new(&self.include, &self.exclude)?;
+        filter.gitignore = self.gitignore;
+        filter.linguist = self.linguist.clone();
+        Ok(filter)
+    }
+}
+
+fn default_gitignore() -> bool {
+    true
+}
+
+//Decides which files of a repository are indexed
+//Files in ignored directories or with ignored extensions are never indexed
+//If there are `include` globs, a file has to match one of them. Files matching an `exclude` glob are skipped
+//The repository's own `.gitignore` and `.gitattributes` files are applied by `is_indexed`
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    gitignore: bool,
+    linguist: Vec<LinguistState>,
+}
+
+impl Default for PathFilter {
+    fn default() -> Self {
+        PathFilter::new(&[], &[]).expect("The default globs are valid")
+    }
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<PathFilter> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(glob_set(include.iter().map(String::as_str))?)
+        };
+
+        let ignored_directories = IGNORED_DIRECTORIES
+            .iter()
+            .map(|directory| format!("{directory}/"));
+        let ignored_extensions = IGNORED_EXTENSIONS
+            .iter()
+            .map(|extension| format!("*.{extension}"));
+        let default_exclude: Vec<String> = ignored_directories.chain(ignored_extensions).collect();
+        let exclude = glob_set(default_exclude.iter().chain(exclude).map(String::as_str))?;
+
+        Ok(PathFilter {
+            include,
+            exclude,
+            gitignore: default_gitignore(),
+            linguist: LinguistState::excluded_by_default(),
+        })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        let is_included = match &self.include {
+            Some(include) => include.is_match(path),
+            None => true,
+        };
+        is_included && !self.exclude.is_match(path)
+    }
+
+    pub fn skips_generated(&self) -> bool {
+        self.linguist
+            .contains(&LinguistState::set(LinguistAttribute::Generated))
+    }
+
+    //Whether a file of a repository with the given `.gitignore` and `.gitattributes` rules is indexed
+    pub fn is_indexed(&self, path: &str, git_rules: &GitRules) -> bool {
+        if !self.is_match(path) || (self.gitignore && git_rules.is_ignored(path)) {
+            return false;
+        }
+        !git_rules
+            .linguist_attributes(path)
+            .iter()
+            .any(|state| self.linguist.contains(state))
+    }
+}
+
+fn glob_set<'a>(patterns: impl Iterator<Item = &'a str>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        for glob in path_globs("", pattern, true)? {
+            builder.add(glob);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+//Patterns follow `.gitignore` conventions
+//A pattern matches a file, or every file in a directory, with a path or name that matches it
+//A pattern without a `/` matches names at any depth, a leading `/` anchors it to the repository root
+//A trailing `/` only matches directories
+//Patterns read from a `.gitignore` or `.gitattributes` file are relative to its `base` directory
+//Non `recursive` patterns, as in `.gitattributes`, don't match the files in a matching directory
+pub(super) fn path_globs(base: &str, pattern: &str, recursive: bool) -> Result<Vec<Glob>> {
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return Err(anyhow::anyhow!("Empty glob pattern"));
+    }
+    let (pattern, is_directory) = match pattern.strip_suffix('/') {
+        Some(directory) => (directory, true),
+        None => (pattern, false),
+    };
+    let base = if base.is_empty() {
+        String::new()
+    } else {
+        format!("{base}/")
+    };
+    let pattern = match pattern.strip_prefix('/') {
+        Some(anchored) => format!("{base}{anchored}"),
+        None if !pattern.contains('/') => format!("{base}**/{pattern}"),
+        None => format!("{base}{pattern}"),
+    };
+
+    let mut patterns = Vec::new();
+    if recursive {
+        patterns.push(format!("{pattern}/**"));
+    }
+    if !is_directory {
+        patterns.push(pattern);
+    }
+    patterns
+        .iter()
+        .map(|pattern| {
+            GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| anyhow::anyhow!("Invalid glob pattern {pattern}: {e}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn test_default_path_filter() {
+        let filter = PathFilter::default();
+        for ext in IGNORED_EXTENSIONS {
+            let path = format!("path/to/file.{}", ext);
+            assert!(!filter.is_match(&path));
+        }
+        for dir in IGNORED_DIRECTORIES {
+            let path = format!("path/to/{}/file.txt", dir);
+            assert!(!filter.is_match(&path));
+            let path = format!("{}/file.txt", dir);
+            assert!(!filter.is_match(&path));
+        }
+
+        assert!(filter.is_match("path/to/file.tsx"));
+        //Directories and extensions are matched as whole path segments
+        assert!(filter.is_match("src/debugger/mod.rs"));
+        assert!(filter.is_match("robinhood/main.py"));
+        assert!(filter.is_match("objects.py"));
+        assert!(filter.is_match("src/changelog"));
+        assert!(filter.is_match("blog/post.md"));
+        assert!(filter.is_match("src/bin.rs"));
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let filter = PathFilter::new(
+            &patterns(&["src/", "*.md"]),
+            &patterns(&["/src/generated/", "**/*_test.rs", "internal"]),
+        )
+        .unwrap();
+
+        assert!(filter.is_match("src/main.rs"));
+        assert!(filter.is_match("src/nested/lib.rs"));
+        assert!(filter.is_match("README.md"));
+        assert!(filter.is_match("docs/guide/setup.md"));
+        assert!(!filter.is_match("scripts/build.sh"));
+        assert!(!filter.is_match("src/generated/schema.rs"));
+        assert!(!filter.is_match("src/parser_test.rs"));
+        assert!(!filter.is_match("src/internal/mod.rs"));
+        assert!(!filter.is_match("src/target/main.rs"));
+
+        //`*` doesn't match across directories
+        let filter = PathFilter::new(&patterns(&["src/*.rs"]), &[]).unwrap();
+        assert!(filter.is_match("src/main.rs"));
+        assert!(!filter.is_match("src/nested/lib.rs"));
+
+        assert!(PathFilter::new(&patterns(&["src/[.rs"]), &[]).is_err());
+        assert!(PathFilter::new(&[], &patterns(&[""])).is_err());
+    }
+
+    #[test]
+    fn test_linguist_rules() {
+        let git_rules = GitRules::new(&[(
+            ".gitattributes".to_string(),
+            "*.pb.go linguist-generated\ndocs/** linguist-documentation\ndocs/examples/** linguist-documentation=false\n".to_string(),
+        )]);
+        let rules: PathRules =
+            serde_json::from_str(r#"{"linguist": ["generated", "documentation=false"]}"#).unwrap();
+        let filter = rules.filter().unwrap();
+
+        assert!(!filter.is_indexed("api/service.pb.go", &git_rules));
+        assert!(!filter.is_indexed("docs/examples/main.go", &git_rules));
+        assert!(filter.is_indexed("docs/guide.md", &git_rules));
+        assert!(filter.is_indexed("src/main.go", &git_rules));
+        assert!(filter.skips_generated());
+    }
+}
@@ -0,0 +1,296 @@
+use globset::{GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::filter::path_globs;
+
+//Linguist attributes of `.gitattributes` files
+//Reference: https://github.com/github-linguist/linguist/blob/master/docs/overrides.md
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinguistAttribute {
+    Generated,
+    Vendored,
+    Documentation,
+}
+
+impl LinguistAttribute {
+    fn from_name(name: &str) -> Option<LinguistAttribute> {
+        match name {
+            "linguist-generated" => Some(LinguistAttribute::Generated),
+            "linguist-vendored" => Some(LinguistAttribute::Vendored),
+            "linguist-documentation" => Some(LinguistAttribute::Documentation),
+            _ => None,
+        }
+    }
+}
+
+//A linguist attribute that a file sets, or explicitly unsets if not `is_set`
+//Written as in the `linguist` rules of `/embed`, e.g. `generated` or `documentation=false`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LinguistState {
+    pub attribute: LinguistAttribute,
+    pub is_set: bool,
+}
+
+impl LinguistState {
+    pub fn set(attribute: LinguistAttribute) -> Self {
+        Self {
+            attribute,
+            is_set: true,
+        }
+    }
+
+    pub fn excluded_by_default() -> Vec<LinguistState> {
+        vec![
+            LinguistState::set(LinguistAttribute::Generated),
+            LinguistState::set(LinguistAttribute::Vendored),
+        ]
+    }
+}
+
+impl TryFrom<String> for LinguistState {
+    type Error = String;
+
+    fn try_from(rule: String) -> Result<Self, Self::Error> {
+        let (name, is_set) = match rule.split_once('=') {
+            Some((name, "true")) => (name, true),
+            Some((name, "false")) => (name, false),
+            Some(_) => return Err(format!("Invalid linguist rule: {rule}")),
+            None => (rule.as_str(), true),
+        };
+        let attribute = LinguistAttribute::from_name(&format!("linguist-{name}"))
+            .ok_or_else(|| format!("Invalid linguist rule: {rule}"))?;
+        Ok(Self { attribute, is_set })
+    }
+}
+
+impl From<LinguistState> for String {
+    fn from(state: LinguistState) -> String {
+        let name = match state.attribute {
+            LinguistAttribute::Generated => "generated",
+            LinguistAttribute::Vendored => "vendored",
+            LinguistAttribute::Documentation => "documentation",
+        };
+        if state.is_set {
+            name.to_string()
+        } else {
+            format!("{name}=false")
+        }
+    }
+}
+
+//The rules of the `.gitignore` and `.gitattributes` files of a repository
+//Rules of nested files take precedence over the ones of their parent directories, later lines over earlier ones
+#[derive(Debug, Default)]
+pub struct GitRules {
+    ignore: GlobSet,
+    //Whether the pattern of each ignore glob is negated, i.e. re-includes the files it matches
+    ignore_negated: Vec<bool>,
+    attributes: GlobSet,
+    //The linguist attributes that the pattern of each attributes glob sets or unsets, or resets to unspecified if `None`
+    attribute_states: Vec<Vec<(LinguistAttribute, Option<bool>)>>,
+}
+
+impl GitRules {
+    pub fn is_rules_file(path: &str) -> bool {
+        let file_name = path.rsplit('/').next().unwrap_or_default();
+        file_name == ".gitignore" || file_name == ".gitattributes"
+    }
+
+    //Parses the `(path, content)` of the repository's rules files
+    //Invalid patterns are skipped, as git does
+    pub fn new(files: &[(String, String)]) -> GitRules {
+        let mut files: Vec<&(String, String)> = files.iter().collect();
+        files.sort_by_key(|(path, _)| (path.matches('/').count(), path.as_str()));
+
+        let mut ignore = GlobSetBuilder::new();
+        let mut ignore_negated = Vec::new();
+        let mut attributes = GlobSetBuilder::new();
+        let mut attribute_states = Vec::new();
+        for (path, content) in files {
+            let (base, file_name) = path.rsplit_once('/').unwrap_or(("", path));
+            for line in content.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if file_name == ".gitignore" {
+                    let (pattern, negated) = match line.strip_prefix('!') {
+                        Some(pattern) => (pattern, true),
+                        None => (line, false),
+                    };
+                    //A leading `\` escapes a `#` or `!` that's part of the name
+                    let pattern = pattern.strip_prefix('\\').unwrap_or(pattern);
+                    for glob in path_globs(base, pattern, true).unwrap_or_default() {
+                        ignore.add(glob);
+                        ignore_negated.push(negated);
+                    }
+                } else if file_name == ".gitattributes" {
+                    let mut tokens = line.split_whitespace();
+                    let Some(pattern) = tokens.next() else {
+                        continue;
+                    };
+                    //Negated patterns are forbidden, quoted patterns and macros aren't supported
+                    if pattern.starts_with(['!', '"', '[']) {
+                        continue;
+                    }
+                    let states: Vec<(LinguistAttribute, Option<bool>)> =
+                        tokens.filter_map(attribute_state).collect();
+                    if states.is_empty() {
+                        continue;
+                    }
+                    for glob in path_globs(base, pattern, false).unwrap_or_default() {
+                        attributes.add(glob);
+                        attribute_states.push(states.clone());
+                    }
+                }
+            }
+        }
+
+        GitRules {
+            ignore: ignore.build().unwrap_or_default(),
+            ignore_negated,
+            attributes: attributes.build().unwrap_or_default(),
+            attribute_states,
+        }
+    }
+
+    //Unlike git, a negated pattern also re-includes files in an ignored directory
+    pub fn is_ignored(&self, path: &str) -> bool {
+        match self.ignore.matches(path).into_iter().max() {
+            Some(index) => !self.ignore_negated[index],
+            None => false,
+        }
+    }
+
+    //The linguist attributes that the file sets or explicitly unsets
+    pub fn linguist_attributes(&self, path: &str) -> Vec<LinguistState> {
+        let mut matches = self.attributes.matches(path);
+        matches.sort_unstable();
+
+        let mut states: Vec<LinguistState> = Vec::new();
+        for index in matches {
+            for (attribute, is_set) in &self.attribute_states[index] {
+                states.retain(|state| state.attribute != *attribute);
+                if let Some(is_set) = is_set {
+                    states.push(LinguistState {
+                        attribute: *attribute,
+                        is_set: *is_set,
+                    });
+                }
+            }
+        }
+        states
+    }
+}
+
+//`attr` and `attr=true` set an attribute, `-attr` and `attr=false` unset it, `!attr` leaves it unspecified
+fn attribute_state(token: &str) -> Option<(LinguistAttribute, Option<bool>)> {
+    let (name, is_set) = if let Some(name) = token.strip_prefix('!') {
+        (name, None)
+    } else if let Some(name) = token.strip_prefix('-') {
+        (name, Some(false))
+    } else if let Some((name, value)) = token.split_once('=') {
+        (name, Some(value != "false"))
+    } else {
+        (token, Some(true))
+    };
+    Some((LinguistAttribute::from_name(name)?, is_set))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git_rules(files: &[(&str, &str)]) -> GitRules {
+        let files: Vec<(String, String)> = files
+            .iter()
+            .map(|(path, content)| (path.to_string(), content.to_string()))
+            .collect();
+        GitRules::new(&files)
+    }
+
+    #[test]
+    fn test_gitignore() {
+        let rules = git_rules(&[
+            ("web/.gitignore", "!dist/keep.js\n/local.js\n"),
+            (
+                ".gitignore",
+                "# Build output\ndist/\n*.log\n!important.log\n/config.json\n\\#notes\n",
+            ),
+        ]);
+
+        assert!(rules.is_ignored("dist/bundle.js"));
+        assert!(rules.is_ignored("web/dist/bundle.js"));
+        assert!(!rules.is_ignored("web/dist/keep.js"));
+        assert!(rules.is_ignored("logs/debug.log"));
+        assert!(!rules.is_ignored("logs/important.log"));
+        assert!(rules.is_ignored("config.json"));
+        assert!(!rules.is_ignored("src/config.json"));
+        assert!(rules.is_ignored("web/local.js"));
+        assert!(!rules.is_ignored("local.js"));
+        assert!(rules.is_ignored("#notes"));
+        assert!(!rules.is_ignored("src/main.rs"));
+        assert!(!rules.is_ignored(".gitignore"));
+    }
+
+    #[test]
+    fn test_gitattributes() {
+        let rules = git_rules(&[
+            (
+                ".gitattributes",
+                "*.pb.go linguist-generated\nvendor/** linguist-vendored=true\ndocs/** linguist-documentation\n*.rs text eol=lf\n",
+            ),
+            (
+                "docs/.gitattributes",
+                "examples/** -linguist-documentation\nschema.json linguist-generated=false linguist-vendored\ndrafts/** !linguist-documentation\n",
+            ),
+        ]);
+        let unset = |attribute| LinguistState {
+            attribute,
+            is_set: false,
+        };
+
+        assert_eq!(
+            rules.linguist_attributes("api/service.pb.go"),
+            vec![LinguistState::set(LinguistAttribute::Generated)]
+        );
+        assert_eq!(
+            rules.linguist_attributes("vendor/lib/mod.go"),
+            vec![LinguistState::set(LinguistAttribute::Vendored)]
+        );
+        assert_eq!(
+            rules.linguist_attributes("docs/guide.md"),
+            vec![LinguistState::set(LinguistAttribute::Documentation)]
+        );
+        assert_eq!(
+            rules.linguist_attributes("docs/examples/main.rs"),
+            vec![unset(LinguistAttribute::Documentation)]
+        );
+        assert!(rules.linguist_attributes("docs/drafts/intro.md").is_empty());
+        assert_eq!(
+            rules.linguist_attributes("docs/api/schema.json"),
+            vec![
+                LinguistState::set(LinguistAttribute::Documentation),
+                unset(LinguistAttribute::Generated),
+                LinguistState::set(LinguistAttribute::Vendored)
+            ]
+        );
+        assert!(rules.linguist_attributes("src/main.rs").is_empty());
+        //Patterns don't match the files in a matching directory
+        assert!(rules.linguist_attributes("vendor.pb.go/mod.go").is_empty());
+    }
+
+    #[test]
+    fn test_linguist_state_serde() {
+        let states: Vec<LinguistState> =
+            serde_json::from_str(r#"["generated", "vendored=true", "documentation=false"]"#)
+                .unwrap();
+        assert_eq!(
+            serde_json::to_string(&states).unwrap(),
+            r#"["generated","vendored","documentation=false"]"#
+        );
+        assert!(serde_json::from_str::<LinguistState>(r#""linguist-generated""#).is_err());
+        assert!(serde_json::from_str::<LinguistState>(r#""documentation=no""#).is_err());
+    }
+}
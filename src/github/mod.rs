@@ -1,12 +1,28 @@
 #![allow(unused_must_use)]
+mod access;
+mod filter;
+mod git_rules;
+mod providers;
+mod sniff;
+
 use crate::{
+    chunker::{chunk_file, ChunkSizer, FileChunk},
+    constants::INDEX_VERSION,
     embeddings::{Embeddings, EmbeddingsModel},
     prelude::*,
 };
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use std::io::Read;
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use access::*;
+pub use filter::*;
+pub use git_rules::*;
+pub use providers::*;
+pub use sniff::*;
 
 #[derive(Debug, Default, Serialize)]
 pub struct File {
@@ -15,25 +31,108 @@ pub struct File {
     pub length: usize,
 }
 
-impl ToString for File {
-    fn to_string(&self) -> String {
-        format!(
+impl std::fmt::Display for File {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
             "File path: {}\nFile length: {} bytes\nFile content: {}",
             &self.path, &self.length, &self.content
         )
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct FileEmbeddings {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkEmbeddings {
     pub path: String,
+    //Git blob SHA of the file content
+    pub hash: String,
+    pub chunk: FileChunk,
     pub embeddings: Embeddings,
 }
 
 #[derive(Debug)]
 pub struct RepositoryEmbeddings {
     pub repo_id: String,
-    pub file_embeddings: Vec<FileEmbeddings>,
+    pub chunk_embeddings: Vec<ChunkEmbeddings>,
+    //Paths of previously indexed files to be removed
+    pub deleted_paths: Vec<String>,
+    pub metadata: RepositoryMetadata,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RepositoryMetadata {
+    pub owner: String,
+    pub name: String,
+    pub branch: String,
+    #[serde(default)]
+    pub provider: Provider,
+    pub file_count: usize,
+    //Seconds since the Unix epoch
+    pub indexed_at: u64,
+    pub embedding_model: String,
+    pub commit_sha: Option<String>,
+    //The globs the files were selected with
+    #[serde(default)]
+    pub rules: PathRules,
+    //Token-scoped collections are only visible to requests with a token that can read the repository
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub index: IndexSettings,
+}
+
+//How the chunks of a collection were made and indexed
+//Collections indexed differently from the current settings are re-indexed in full
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct IndexSettings {
+    pub version: u32,
+    pub chunk_max_size: usize,
+    pub chunk_overlap: usize,
+}
+
+impl IndexSettings {
+    pub fn new(sizer: &ChunkSizer) -> Self {
+        Self {
+            version: INDEX_VERSION,
+            chunk_max_size: sizer.max_size(),
+            chunk_overlap: sizer.overlap(),
+        }
+    }
+}
+
+impl RepositoryMetadata {
+    pub fn repository(&self) -> Repository {
+        Repository {
+            owner: self.owner.clone(),
+            name: self.name.clone(),
+            branch: self.branch.clone(),
+            provider: self.provider,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RepositoryFiles {
+    pub files: Vec<File>,
+    //Commit SHA of the downloaded archive, if known
+    pub commit_sha: Option<String>,
+    //Whether the repository can only be read with a token
+    pub private: bool,
+    //Files that the rules select but that aren't indexed because of their content
+    pub skipped_files: Vec<SkippedFile>,
+}
+
+//Files to be embedded, relative to an indexed version of the repository
+#[derive(Debug, Default)]
+pub struct RepositoryChanges {
+    //Files that were added or modified
+    pub files: Vec<File>,
+    //Paths of indexed files that were deleted
+    pub deleted_paths: Vec<String>,
+    //Total number of files in the repository
+    pub file_count: usize,
+    pub commit_sha: Option<String>,
+    pub private: bool,
 }
 
 #[derive(Serialize, Debug)]
@@ -42,98 +141,175 @@ pub struct RepositoryFilePaths {
     pub file_paths: Vec<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Repository {
     pub owner: String,
     pub name: String,
     pub branch: String,
+    #[serde(default)]
+    pub provider: Provider,
 }
 
-impl ToString for Repository {
-    fn to_string(&self) -> String {
-        format!("{}-{}-{}", &self.owner, &self.name, &self.branch)
+impl std::fmt::Display for Repository {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.provider {
+            //GitHub repositories keep the IDs they were indexed with before providers were added
+            Provider::GitHub => write!(f, "{}-{}-{}", &self.owner, &self.name, &self.branch),
+            //Owners, names and branches may contain `-`, so other providers' IDs are a hash of the fields instead
+            //`_` isn't valid in GitHub owner names, so that they can't clash with GitHub's IDs
+            provider => {
+                let fields =
+                    serde_json::json!([provider.to_string(), self.owner, self.name, self.branch]);
+                let hash =
+                    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, fields.to_string().as_bytes());
+                write!(f, "{}_{}", provider, hash)
+            }
+        }
     }
 }
 
 pub async fn embed_repo<M: EmbeddingsModel + Send + Sync>(
     repository: &Repository,
-    files: Vec<File>,
+    rules: &PathRules,
+    repository_changes: RepositoryChanges,
     model: &M,
 ) -> Result<RepositoryEmbeddings> {
-    let content: Vec<String> = files.par_iter().map(|file| file.content.clone()).collect();
+    let RepositoryChanges {
+        files,
+        deleted_paths,
+        file_count,
+        commit_sha,
+        private,
+    } = repository_changes;
+    let metadata = RepositoryMetadata {
+        owner: repository.owner.clone(),
+        name: repository.name.clone(),
+        branch: repository.branch.clone(),
+        provider: repository.provider,
+        file_count,
+        indexed_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        embedding_model: model.model_name().to_string(),
+        commit_sha,
+        rules: rules.clone(),
+        private,
+        index: IndexSettings::new(model.chunk_sizer()),
+    };
+
+    let chunks: Vec<(String, String, FileChunk)> = files
+        .into_par_iter()
+        .flat_map_iter(|file| {
+            let hash = file_hash(&file.content);
+            chunk_file(&file.path, &file.content, model.chunk_sizer())
+                .into_iter()
+                .map(move |chunk| (file.path.clone(), hash.clone(), chunk))
+        })
+        .collect();
+
+    let content: Vec<&str> = chunks
+        .iter()
+        .map(|(_, _, chunk)| chunk.content.as_str())
+        .collect();
 
-    let embeddings: Vec<Embeddings> = model.embed(content)?;
+    let embeddings: Vec<Embeddings> = if content.is_empty() {
+        Vec::new()
+    } else {
+        model.embed(content)?
+    };
 
-    let file_embeddings: Vec<FileEmbeddings> = embeddings
+    let chunk_embeddings: Vec<ChunkEmbeddings> = embeddings
         .into_par_iter()
-        .zip(files.into_par_iter())
-        .map(|(embeddings, file)| FileEmbeddings {
-            path: file.path,
+        .zip(chunks.into_par_iter())
+        .map(|(embeddings, (path, hash, chunk))| ChunkEmbeddings {
+            path,
+            hash,
+            chunk,
             embeddings,
         })
         .collect();
 
     Ok(RepositoryEmbeddings {
         repo_id: repository.to_string(),
-        file_embeddings,
+        chunk_embeddings,
+        deleted_paths,
+        metadata,
     })
 }
 
-pub async fn fetch_repo_files(repository: &Repository) -> Result<Vec<File>> {
-    let Repository {
-        owner,
-        name,
-        branch,
-    } = repository;
-
-    let url = format!("https://github.com/{owner}/{name}/archive/{branch}.zip");
-    let response = reqwest::get(url).await?.bytes().await?;
-
-    let reader = std::io::Cursor::new(response);
-    let mut archive = zip::ZipArchive::new(reader)?;
-
-    let files: Vec<File> = (0..archive.len())
-        .filter_map(|file| {
-            let mut file = archive.by_index(file).unwrap();
-            let file_path = file.name().split_once('/').unwrap().1.to_string();
-
-            if file.is_file() && should_index(&file_path) {
-                let mut content = String::new();
-                let length = content.len();
-                //Fails for non UTF-8 files
-                match file.read_to_string(&mut content) {
-                    Ok(_) => Some(File {
-                        path: file_path,
-                        content,
-                        length,
-                    }),
-                    Err(_) => None,
-                }
-            } else {
-                None
-            }
-        })
+//Compares the fetched files against the hashes of the indexed files
+//An empty `indexed_hashes` marks every file as changed
+pub fn diff_repo_files(
+    repository_files: RepositoryFiles,
+    indexed_hashes: &HashMap<String, String>,
+) -> RepositoryChanges {
+    let RepositoryFiles {
+        files,
+        commit_sha,
+        private,
+        ..
+    } = repository_files;
+    let file_count = files.len();
+
+    let paths: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+    let mut deleted_paths: Vec<String> = indexed_hashes
+        .keys()
+        .filter(|path| !paths.contains(path.as_str()))
+        .cloned()
         .collect();
-    Ok(files)
-}
+    deleted_paths.sort();
 
-pub async fn fetch_file_content(repository: &Repository, path: &str) -> Result<String> {
-    let Repository {
-        owner: repo_owner,
-        name: repo_name,
-        branch: repo_branch,
-    } = repository;
-    let url =
-        format!("https://raw.githubusercontent.com/{repo_owner}/{repo_name}/{repo_branch}/{path}");
-    let response = reqwest::get(url).await?;
-    if response.status() == reqwest::StatusCode::OK {
-        let content = response.text().await?;
-        Ok(content)
-    } else {
-        Err(anyhow::anyhow!("Unable to fetch file content"))
+    let files: Vec<File> = files
+        .into_par_iter()
+        .filter(|file| indexed_hashes.get(&file.path) != Some(&file_hash(&file.content)))
+        .collect();
+
+    RepositoryChanges {
+        files,
+        deleted_paths,
+        file_count,
+        commit_sha,
+        private,
     }
 }
 
+//Git blob SHA of the content, as listed in the repository's tree
+pub fn file_hash(content: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", content.len()));
+    hasher.update(content);
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub async fn fetch_repo_files(
+    repository: &Repository,
+    filter: &PathFilter,
+    github_token: Option<&str>,
+) -> Result<RepositoryFiles> {
+    repository
+        .provider
+        .source(github_token)
+        .fetch_repo_files(repository, filter)
+        .await
+}
+
+//Reads a file at the indexed commit if it's known, so that it matches the indexed chunks
+pub async fn fetch_file_content(
+    repository: &Repository,
+    commit_sha: Option<&str>,
+    path: &str,
+    github_token: Option<&str>,
+) -> Result<String> {
+    let git_ref = commit_sha.unwrap_or(&repository.branch);
+    repository
+        .provider
+        .source(github_token)
+        .fetch_file_content(repository, git_ref, path)
+        .await
+}
+
 const IGNORED_EXTENSIONS: &[&str] = &[
     "bpg", "eps", "pcx", "ppm", "tga", "tiff", "wmf", "xpm", "svg", "ttf", "woff2", "fnt", "fon",
     "otf", "pdf", "ps", "dot", "docx", "dotx", "xls", "xlsx", "xlt", "lock", "odt", "ott", "ods",
@@ -172,45 +348,15 @@ pub struct LicenseFetchResponse {
     pub error: Option<Value>,
 }
 
-pub async fn fetch_license_info(repository: &Repository) -> Result<LicenseFetchResponse> {
-    let Repository { owner, name, .. } = repository;
-    let url = format!("https://api.github.com/repos/{owner}/{name}/license");
-
-    //User-agent reference: https://docs.github.com/en/rest/overview/resources-in-the-rest-api?apiVersion=2022-11-28#user-agent-required
-    let client = reqwest::Client::builder()
-        .user_agent("open-sauced")
-        .build()
-        .unwrap();
-
-    let response = client.get(url).send().await?;
-    match response.error_for_status() {
-        Ok(response) => {
-            let response_json = response.json::<Value>().await?;
-            let license_key = response_json["license"]["key"].as_str().unwrap_or_default();
-            let permissible: bool = ALLOWED_LICENSES.iter().any(|k| k.eq(&license_key));
-
-            Ok(LicenseFetchResponse {
-                permissible,
-                error: if permissible {
-                    None
-                } else {
-                    Some(json! {{
-                        "message": "Impermissible repository license",
-                        "license": {
-                            "name": response_json["license"]["name"],
-                            "url": response_json["html_url"]
-                        }
-                    }})
-                },
-            })
-        }
-        Err(_) => Err(anyhow::anyhow!("Unable to fetch repository license")),
-    }
-}
-
-pub fn should_index(path: &str) -> bool {
-    !(IGNORED_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
-        || IGNORED_DIRECTORIES.iter().any(|dir| path.contains(dir)))
+pub async fn fetch_license_info(
+    repository: &Repository,
+    github_token: Option<&str>,
+) -> Result<LicenseFetchResponse> {
+    repository
+        .provider
+        .source(github_token)
+        .fetch_license_info(repository)
+        .await
 }
 
 #[cfg(test)]
@@ -223,14 +369,16 @@ mod tests {
             owner: "open-sauced".to_string(),
             name: "ai".to_string(),
             branch: "beta".to_string(),
+            provider: Provider::GitHub,
         };
 
-        let result = fetch_repo_files(&repository).await;
+        let result = fetch_repo_files(&repository, &PathFilter::default(), None).await;
 
         // Assert that the function returns a Result containing a vector of File
         assert!(result.is_ok());
-        let files = result.unwrap();
-        assert!(!files.is_empty());
+        let repository_files = result.unwrap();
+        assert!(!repository_files.files.is_empty());
+        assert!(repository_files.commit_sha.is_some());
     }
 
     #[tokio::test]
@@ -239,10 +387,11 @@ mod tests {
             owner: "open-sauced".to_string(),
             name: "ai".to_string(),
             branch: "beta".to_string(),
+            provider: Provider::GitHub,
         };
         let path = "package.json";
 
-        let result = fetch_file_content(&repository, path).await;
+        let result = fetch_file_content(&repository, None, path, None).await;
 
         // Assert that the function returns a Result containing the file content
         assert!(result.is_ok());
@@ -251,29 +400,50 @@ mod tests {
 
         let path = "Some_Invalid_File.example";
 
-        let result = fetch_file_content(&repository, path).await;
+        let result = fetch_file_content(&repository, None, path, None).await;
 
         //Assert that the function returns Err for an invalid file path
         assert!(result.is_err());
     }
 
     #[test]
-    fn test_should_index() {
-        // Test with ignored extensions
-        for ext in IGNORED_EXTENSIONS {
-            let path = format!("path/to/file.{}", ext);
-            assert!(!should_index(&path));
-        }
-
-        // Test with ignored directories
-        for dir in IGNORED_DIRECTORIES {
-            let path = format!("path/to/{}/file.txt", dir);
-            assert!(!should_index(&path));
-        }
+    fn test_file_hash() {
+        //Matches `git hash-object`
+        assert_eq!(
+            file_hash("hello world\n"),
+            "3b18e512dba79e4c8300dd08aeb37f8e728b8dad"
+        );
+    }
 
-        // Test with valid path
-        let path = "path/to/file.tsx";
-        assert!(should_index(path));
+    #[test]
+    fn test_diff_repo_files() {
+        let file = |path: &str, content: &str| File {
+            path: path.to_string(),
+            content: content.to_string(),
+            length: content.len(),
+        };
+        let repository_files = RepositoryFiles {
+            files: vec![
+                file("unchanged.rs", "fn main() {}"),
+                file("modified.rs", "fn modified() {}"),
+                file("added.rs", "fn added() {}"),
+            ],
+            commit_sha: None,
+            private: false,
+            skipped_files: Vec::new(),
+        };
+        let indexed_hashes = HashMap::from([
+            ("unchanged.rs".to_string(), file_hash("fn main() {}")),
+            ("modified.rs".to_string(), file_hash("fn original() {}")),
+            ("deleted.rs".to_string(), file_hash("fn deleted() {}")),
+        ]);
+
+        let changes = diff_repo_files(repository_files, &indexed_hashes);
+        let changed_paths: Vec<&str> = changes.files.iter().map(|f| f.path.as_str()).collect();
+
+        assert_eq!(changed_paths, vec!["modified.rs", "added.rs"]);
+        assert_eq!(changes.deleted_paths, vec!["deleted.rs".to_string()]);
+        assert_eq!(changes.file_count, 3);
     }
 
     #[tokio::test]
@@ -283,9 +453,12 @@ mod tests {
             owner: "open-sauced".to_string(),
             name: "ai".to_string(),
             branch: "beta".to_string(),
+            provider: Provider::GitHub,
         };
 
-        let license_info = fetch_license_info(&repository).await.unwrap_or_default();
+        let license_info = fetch_license_info(&repository, None)
+            .await
+            .unwrap_or_default();
         assert!(license_info.permissible);
 
         //Permissible
@@ -293,9 +466,12 @@ mod tests {
             owner: "facebook".to_string(),
             name: "react".to_string(),
             branch: "main".to_string(),
+            provider: Provider::GitHub,
         };
 
-        let license_info = fetch_license_info(&repository).await.unwrap_or_default();
+        let license_info = fetch_license_info(&repository, None)
+            .await
+            .unwrap_or_default();
         assert!(license_info.permissible);
 
         //Impermissible
@@ -303,9 +479,12 @@ mod tests {
             owner: "open-sauced".to_string(),
             name: "guestbook".to_string(),
             branch: "main".to_string(),
+            provider: Provider::GitHub,
         };
 
-        let license_info = fetch_license_info(&repository).await.unwrap_or_default();
+        let license_info = fetch_license_info(&repository, None)
+            .await
+            .unwrap_or_default();
         assert!(!license_info.permissible);
     }
 }
@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use reqwest::Url;
+use serde_json::Value;
+
+use super::{get, license_from_file, read_archive, url_with_segments, RepositorySource};
+use crate::{
+    github::{LicenseFetchResponse, PathFilter, Repository, RepositoryFiles},
+    prelude::*,
+};
+
+//Bitbucket Cloud, the owner is the workspace of the repository
+pub struct BitbucketSource;
+
+#[async_trait]
+impl RepositorySource for BitbucketSource {
+    async fn fetch_repo_files(
+        &self,
+        repository: &Repository,
+        filter: &PathFilter,
+    ) -> Result<RepositoryFiles> {
+        let Repository { owner, name, .. } = repository;
+        let commit_sha = self.resolve_commit(repository).await?;
+        let url = format!("https://bitbucket.org/{owner}/{name}/get/{commit_sha}.zip");
+
+        let response = get(Url::parse(&url)?).await?.bytes().await?;
+        let mut repository_files = read_archive(response.to_vec(), filter)?;
+        repository_files.commit_sha = Some(commit_sha);
+        Ok(repository_files)
+    }
+
+    async fn fetch_file_content(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+    ) -> Result<String> {
+        let mut segments = vec!["src", git_ref];
+        segments.extend(path.split('/'));
+        let url = self.repo_url(repository, &segments)?;
+
+        match get(url).await {
+            Ok(response) => Ok(response.text().await?),
+            Err(_) => Err(anyhow::anyhow!("Unable to fetch file content")),
+        }
+    }
+
+    //Bitbucket doesn't detect repository licenses
+    async fn fetch_license_info(&self, repository: &Repository) -> Result<LicenseFetchResponse> {
+        license_from_file(self, repository).await
+    }
+
+    fn file_url(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+        start_line: usize,
+        end_line: usize,
+    ) -> Option<String> {
+        let Repository { owner, name, .. } = repository;
+        Some(format!(
+            "https://bitbucket.org/{owner}/{name}/src/{git_ref}/{path}#lines-{start_line}:{end_line}"
+        ))
+    }
+}
+
+impl BitbucketSource {
+    fn repo_url(&self, repository: &Repository, segments: &[&str]) -> Result<Url> {
+        let mut path = vec!["repositories", &repository.owner, &repository.name];
+        path.extend(segments);
+        url_with_segments("https://api.bitbucket.org/2.0", &path)
+    }
+
+    //SHA of the commit that the branch, tag or SHA of the repository refers to
+    async fn resolve_commit(&self, repository: &Repository) -> Result<String> {
+        let url = self.repo_url(repository, &["commit", &repository.branch])?;
+        let response_json = get(url).await?.json::<Value>().await?;
+        response_json["hash"]
+            .as_str()
+            .map(String::from)
+            .ok_or(anyhow::anyhow!("Unable to resolve the commit"))
+    }
+}
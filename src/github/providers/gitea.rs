@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use reqwest::Url;
+use serde_json::{json, Value};
+
+use super::{
+    get, host_url, license_from_file, license_response, parse_commit_sha, read_archive,
+    url_with_segments, RepositorySource,
+};
+use crate::{
+    constants::GITEA_URL_DEFAULT,
+    github::{LicenseFetchResponse, PathFilter, Repository, RepositoryFiles, ALLOWED_LICENSES},
+    prelude::*,
+};
+
+//A Gitea or Forgejo instance at `GITEA_URL`
+pub struct GiteaSource {
+    url: String,
+}
+
+#[async_trait]
+impl RepositorySource for GiteaSource {
+    async fn fetch_repo_files(
+        &self,
+        repository: &Repository,
+        filter: &PathFilter,
+    ) -> Result<RepositoryFiles> {
+        let commit_sha = self.resolve_commit(repository).await?;
+        let url = self.repo_url(repository, &["archive", &format!("{commit_sha}.zip")])?;
+
+        let response = get(url).await?.bytes().await?;
+        let mut repository_files = read_archive(response.to_vec(), filter)?;
+        repository_files.commit_sha = Some(commit_sha);
+        Ok(repository_files)
+    }
+
+    async fn fetch_file_content(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+    ) -> Result<String> {
+        let mut segments = vec!["raw"];
+        segments.extend(path.split('/'));
+        let mut url = self.repo_url(repository, &segments)?;
+        url.query_pairs_mut().append_pair("ref", git_ref);
+
+        match get(url).await {
+            Ok(response) => Ok(response.text().await?),
+            Err(_) => Err(anyhow::anyhow!("Unable to fetch file content")),
+        }
+    }
+
+    async fn fetch_license_info(&self, repository: &Repository) -> Result<LicenseFetchResponse> {
+        //The licenses API was added in Gitea 1.22, older instances fall back to the license file
+        let licenses = match get(self.repo_url(repository, &["licenses"])?).await {
+            Ok(response) => response.json::<Vec<String>>().await.unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+        if licenses.is_empty() {
+            return license_from_file(self, repository).await;
+        }
+
+        //Dual-licensed repositories can be used under any of their licenses
+        let license_key = licenses
+            .iter()
+            .map(|license| license.to_lowercase())
+            .find(|license| ALLOWED_LICENSES.contains(&license.as_str()))
+            .unwrap_or_default();
+        Ok(license_response(
+            &license_key,
+            json!(licenses.join(", ")),
+            Value::Null,
+        ))
+    }
+
+    fn file_url(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+        start_line: usize,
+        end_line: usize,
+    ) -> Option<String> {
+        let Repository { owner, name, .. } = repository;
+        let ref_type = if parse_commit_sha(git_ref.as_bytes()).is_some() {
+            "commit"
+        } else {
+            "branch"
+        };
+        Some(format!(
+            "{}/{owner}/{name}/src/{ref_type}/{git_ref}/{path}#L{start_line}-L{end_line}",
+            self.url
+        ))
+    }
+}
+
+impl GiteaSource {
+    pub fn initialize() -> Self {
+        Self::new(&host_url("GITEA_URL", GITEA_URL_DEFAULT))
+    }
+
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn repo_url(&self, repository: &Repository, segments: &[&str]) -> Result<Url> {
+        let mut path = vec!["api", "v1", "repos", &repository.owner, &repository.name];
+        path.extend(segments);
+        url_with_segments(&self.url, &path)
+    }
+
+    //SHA of the commit that the branch, tag or SHA of the repository refers to
+    async fn resolve_commit(&self, repository: &Repository) -> Result<String> {
+        let url = self.repo_url(repository, &["git", "commits", &repository.branch])?;
+        let response_json = get(url).await?.json::<Value>().await?;
+        response_json["sha"]
+            .as_str()
+            .map(String::from)
+            .ok_or(anyhow::anyhow!("Unable to resolve the commit"))
+    }
+}
@@ -0,0 +1,171 @@
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{license_response, parse_commit_sha, read_archive, RepositorySource};
+use crate::{
+    github::{LicenseFetchResponse, PathFilter, Repository, RepositoryFiles},
+    prelude::*,
+};
+
+//Reads public repositories anonymously and private repositories with a token
+pub struct GitHubSource {
+    token: Option<String>,
+}
+
+#[async_trait]
+impl RepositorySource for GitHubSource {
+    async fn fetch_repo_files(
+        &self,
+        repository: &Repository,
+        filter: &PathFilter,
+    ) -> Result<RepositoryFiles> {
+        let Repository {
+            owner,
+            name,
+            branch,
+            ..
+        } = repository;
+
+        //Archives of private repositories are only served by the API, which redirects to a signed URL
+        let url = match self.token {
+            Some(_) => format!("https://api.github.com/repos/{owner}/{name}/zipball/{branch}"),
+            None => format!("https://github.com/{owner}/{name}/archive/{branch}.zip"),
+        };
+        let response = self.get(&url).send().await?.error_for_status()?;
+        let mut repository_files = read_archive(response.bytes().await?.to_vec(), filter)?;
+        if repository_files.commit_sha.is_none() {
+            repository_files.commit_sha = Some(self.resolve_commit(repository).await?);
+        }
+        repository_files.private = self.is_private(repository).await?;
+        Ok(repository_files)
+    }
+
+    async fn fetch_file_content(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+    ) -> Result<String> {
+        let Repository {
+            owner: repo_owner,
+            name: repo_name,
+            ..
+        } = repository;
+        let url =
+            format!("https://raw.githubusercontent.com/{repo_owner}/{repo_name}/{git_ref}/{path}");
+        let response = self.get(&url).send().await?;
+        if response.status() == reqwest::StatusCode::OK {
+            let content = response.text().await?;
+            Ok(content)
+        } else {
+            Err(anyhow::anyhow!("Unable to fetch file content"))
+        }
+    }
+
+    async fn fetch_license_info(&self, repository: &Repository) -> Result<LicenseFetchResponse> {
+        let Repository { owner, name, .. } = repository;
+        let url = format!("https://api.github.com/repos/{owner}/{name}/license");
+
+        let response = self.get(&url).send().await?;
+        match response.error_for_status() {
+            Ok(response) => {
+                let response_json = response.json::<Value>().await?;
+                let license_key = response_json["license"]["key"].as_str().unwrap_or_default();
+
+                Ok(license_response(
+                    license_key,
+                    response_json["license"]["name"].clone(),
+                    response_json["html_url"].clone(),
+                ))
+            }
+            Err(_) => Err(anyhow::anyhow!("Unable to fetch repository license")),
+        }
+    }
+
+    async fn can_read(&self, repository: &Repository) -> Result<bool> {
+        let Repository { owner, name, .. } = repository;
+        let url = format!("https://api.github.com/repos/{owner}/{name}");
+
+        //Repositories that the token can't read are reported as not found
+        let response = self.get(&url).send().await?;
+        Ok(response.status().is_success())
+    }
+
+    fn file_url(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+        start_line: usize,
+        end_line: usize,
+    ) -> Option<String> {
+        let Repository { owner, name, .. } = repository;
+        Some(format!(
+            "https://github.com/{owner}/{name}/blob/{git_ref}/{path}#L{start_line}-L{end_line}"
+        ))
+    }
+}
+
+impl GitHubSource {
+    //Uses the per-request token if there is one, the server's `GITHUB_TOKEN` otherwise
+    pub fn initialize(token: Option<&str>) -> Self {
+        let token = match token {
+            Some(token) => Some(token.to_string()),
+            None => std::env::var("GITHUB_TOKEN").ok(),
+        };
+        Self::new(token)
+    }
+
+    pub fn new(token: Option<String>) -> Self {
+        Self {
+            token: token.filter(|token| !token.is_empty()),
+        }
+    }
+
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        //User-agent reference: https://docs.github.com/en/rest/overview/resources-in-the-rest-api?apiVersion=2022-11-28#user-agent-required
+        let client = reqwest::Client::builder()
+            .user_agent("open-sauced")
+            .build()
+            .unwrap();
+
+        let request = client.get(url);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    //SHA of the commit that the branch, tag or SHA of the repository refers to
+    async fn resolve_commit(&self, repository: &Repository) -> Result<String> {
+        let Repository {
+            owner,
+            name,
+            branch,
+            ..
+        } = repository;
+        let url = format!("https://api.github.com/repos/{owner}/{name}/commits/{branch}");
+
+        let response = self
+            .get(&url)
+            .header(reqwest::header::ACCEPT, "application/vnd.github.sha")
+            .send()
+            .await?
+            .error_for_status()?;
+        parse_commit_sha(&response.bytes().await?)
+            .ok_or(anyhow::anyhow!("Unable to resolve the commit"))
+    }
+
+    //Anonymous requests can only read public repositories
+    async fn is_private(&self, repository: &Repository) -> Result<bool> {
+        if self.token.is_none() {
+            return Ok(false);
+        }
+        let Repository { owner, name, .. } = repository;
+        let url = format!("https://api.github.com/repos/{owner}/{name}");
+
+        let response = self.get(&url).send().await?.error_for_status()?;
+        let response_json = response.json::<Value>().await?;
+        Ok(response_json["private"].as_bool().unwrap_or_default())
+    }
+}
@@ -0,0 +1,175 @@
+use async_trait::async_trait;
+use reqwest::Url;
+use serde_json::Value;
+
+use super::{get, host_url, license_response, read_archive, url_with_segments, RepositorySource};
+use crate::{
+    constants::GITLAB_URL_DEFAULT,
+    github::{LicenseFetchResponse, PathFilter, Repository, RepositoryFiles},
+    prelude::*,
+};
+
+//GitLab.com or a self-hosted instance at `GITLAB_URL`
+//The owner is the namespace of the project, including any subgroups
+pub struct GitLabSource {
+    url: String,
+}
+
+#[async_trait]
+impl RepositorySource for GitLabSource {
+    async fn fetch_repo_files(
+        &self,
+        repository: &Repository,
+        filter: &PathFilter,
+    ) -> Result<RepositoryFiles> {
+        //Archives are downloaded at the resolved commit, since GitLab doesn't set the archive comment
+        let commit_sha = self.resolve_commit(repository).await?;
+        let mut url = self.project_url(repository, &["repository", "archive.zip"])?;
+        url.query_pairs_mut().append_pair("sha", &commit_sha);
+
+        let response = get(url).await?.bytes().await?;
+        let mut repository_files = read_archive(response.to_vec(), filter)?;
+        repository_files.commit_sha = Some(commit_sha);
+        Ok(repository_files)
+    }
+
+    async fn fetch_file_content(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+    ) -> Result<String> {
+        let mut url = self.project_url(repository, &["repository", "files", path, "raw"])?;
+        url.query_pairs_mut().append_pair("ref", git_ref);
+
+        match get(url).await {
+            Ok(response) => Ok(response.text().await?),
+            Err(_) => Err(anyhow::anyhow!("Unable to fetch file content")),
+        }
+    }
+
+    async fn fetch_license_info(&self, repository: &Repository) -> Result<LicenseFetchResponse> {
+        let mut url = self.project_url(repository, &[])?;
+        url.query_pairs_mut().append_pair("license", "true");
+
+        let response_json = match get(url).await {
+            Ok(response) => response.json::<Value>().await?,
+            Err(_) => return Err(anyhow::anyhow!("Unable to fetch repository license")),
+        };
+        //Projects without a detected license have a null license
+        let Some(license_key) = response_json["license"]["key"].as_str() else {
+            return Err(anyhow::anyhow!("Unable to fetch repository license"));
+        };
+
+        Ok(license_response(
+            license_key,
+            response_json["license"]["name"].clone(),
+            response_json["license_url"].clone(),
+        ))
+    }
+
+    fn file_url(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+        start_line: usize,
+        end_line: usize,
+    ) -> Option<String> {
+        let Repository { owner, name, .. } = repository;
+        Some(format!(
+            "{}/{owner}/{name}/-/blob/{git_ref}/{path}#L{start_line}-{end_line}",
+            self.url
+        ))
+    }
+}
+
+impl GitLabSource {
+    pub fn initialize() -> Self {
+        Self::new(&host_url("GITLAB_URL", GITLAB_URL_DEFAULT))
+    }
+
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    //Projects are identified by their URL-encoded full path in the API
+    fn project_url(&self, repository: &Repository, segments: &[&str]) -> Result<Url> {
+        let project = format!("{}/{}", repository.owner, repository.name);
+        let mut path = vec!["api", "v4", "projects", project.as_str()];
+        path.extend(segments);
+        url_with_segments(&self.url, &path)
+    }
+
+    //SHA of the commit that the branch, tag or SHA of the repository refers to
+    async fn resolve_commit(&self, repository: &Repository) -> Result<String> {
+        let url = self.project_url(repository, &["repository", "commits", &repository.branch])?;
+        let response_json = get(url).await?.json::<Value>().await?;
+        commit_id(&response_json)
+    }
+}
+
+//The commits API returns the commit itself, with its SHA as `id`
+fn commit_id(response_json: &Value) -> Result<String> {
+    response_json["id"]
+        .as_str()
+        .map(String::from)
+        .ok_or(anyhow::anyhow!("Unable to resolve the commit"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Provider;
+
+    #[test]
+    fn test_project_url() {
+        let repository = Repository {
+            owner: "group/subgroup".to_string(),
+            name: "repo".to_string(),
+            branch: "feature/x".to_string(),
+            provider: Provider::GitLab,
+        };
+        let source = GitLabSource::new("https://gitlab.example.com/gitlab/");
+
+        let url = source
+            .project_url(&repository, &["repository", "branches", &repository.branch])
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://gitlab.example.com/gitlab/api/v4/projects/group%2Fsubgroup%2Frepo/repository/branches/feature%2Fx"
+        );
+    }
+
+    #[test]
+    fn test_commit_id() {
+        //A response of `GET /projects/:id/repository/commits/:sha`, as documented by GitLab
+        let response_json: Value = serde_json::from_str(
+            r#"{
+                "id": "6104942438c14ec7bd21c6cd5bd995272b3faff6",
+                "short_id": "6104942438c",
+                "created_at": "2024-03-01T12:02:54.000+00:00",
+                "parent_ids": ["ae1d9fb46aa2b07ee9836d49862ec4e2c46fbbba"],
+                "title": "Merge branch 'docs-update' into 'main'",
+                "message": "Merge branch 'docs-update' into 'main'\n",
+                "author_name": "Jane Doe",
+                "committed_date": "2024-03-01T12:02:54.000+00:00",
+                "stats": { "additions": 15, "deletions": 10, "total": 25 },
+                "status": "success",
+                "last_pipeline": { "id": 8, "ref": "main", "sha": "6104942438c14ec7bd21c6cd5bd995272b3faff6" },
+                "web_url": "https://gitlab.com/gitlab-org/gitlab-runner/-/commit/6104942438c14ec7bd21c6cd5bd995272b3faff6"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            commit_id(&response_json).unwrap(),
+            "6104942438c14ec7bd21c6cd5bd995272b3faff6"
+        );
+
+        let not_found: Value =
+            serde_json::from_str(r#"{ "message": "404 Commit Not Found" }"#).unwrap();
+        assert!(commit_id(&not_found).is_err());
+    }
+}
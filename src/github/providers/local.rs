@@ -0,0 +1,361 @@
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use async_trait::async_trait;
+
+use super::{read_archive, RepositorySource};
+use crate::{
+    github::{FileReader, GitRules, LicenseFetchResponse, PathFilter, Repository, RepositoryFiles},
+    prelude::*,
+};
+
+//Repositories checked out on the server at `{LOCAL_REPOSITORIES_PATH}/{owner}/{name}`
+//Git repositories are read at the requested branch, tag or commit, other directories as they are on disk
+pub struct LocalSource {
+    root: Option<PathBuf>,
+}
+
+#[async_trait]
+impl RepositorySource for LocalSource {
+    async fn fetch_repo_files(
+        &self,
+        repository: &Repository,
+        filter: &PathFilter,
+    ) -> Result<RepositoryFiles> {
+        let path = self.repository_path(repository)?;
+        let branch = repository.branch.clone();
+        let filter = filter.clone();
+
+        actix_rt::task::spawn_blocking(move || {
+            if is_git_repository(&path) {
+                let archive = git(
+                    &path,
+                    &["archive", "--format=zip", "--prefix=repository/", &branch],
+                )?;
+                read_archive(archive, &filter)
+            } else {
+                let mut paths: Vec<(String, PathBuf)> = Vec::new();
+                list_directory(&path, &path, &mut paths)?;
+                let rules_files: Vec<(String, String)> = paths
+                    .iter()
+                    .filter(|(relative_path, _)| GitRules::is_rules_file(relative_path))
+                    .filter_map(|(relative_path, path)| {
+                        let content = std::fs::read_to_string(path).ok()?;
+                        Some((relative_path.clone(), content))
+                    })
+                    .collect();
+                let git_rules = GitRules::new(&rules_files);
+
+                let mut reader = FileReader::new(&filter);
+                for (relative_path, path) in paths {
+                    if filter.is_indexed(&relative_path, &git_rules) {
+                        let size = std::fs::metadata(&path)?.len();
+                        reader.read(relative_path, size, || std::fs::read(&path));
+                    }
+                }
+                Ok(RepositoryFiles {
+                    files: reader.files,
+                    commit_sha: None,
+                    private: false,
+                    skipped_files: reader.skipped_files,
+                })
+            }
+        })
+        .await?
+    }
+
+    async fn fetch_file_content(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+    ) -> Result<String> {
+        let repository_path = self.repository_path(repository)?;
+        let is_relative = Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if path.is_empty() || !is_relative || git_ref.starts_with('-') {
+            return Err(anyhow::anyhow!("Unable to fetch file content"));
+        }
+
+        let git_ref = git_ref.to_string();
+        let path = path.to_string();
+        actix_rt::task::spawn_blocking(move || {
+            if is_git_repository(&repository_path) {
+                let content = git(&repository_path, &["show", &format!("{git_ref}:{path}")])?;
+                Ok(String::from_utf8(content)?)
+            } else {
+                Ok(std::fs::read_to_string(repository_path.join(path))?)
+            }
+        })
+        .await?
+    }
+
+    //Local repositories are owned by the server's operator
+    async fn fetch_license_info(&self, _: &Repository) -> Result<LicenseFetchResponse> {
+        Ok(LicenseFetchResponse {
+            permissible: true,
+            error: None,
+        })
+    }
+
+    fn file_url(&self, _: &Repository, _: &str, _: &str, _: usize, _: usize) -> Option<String> {
+        None
+    }
+}
+
+impl LocalSource {
+    pub fn initialize() -> Self {
+        let root = std::env::var("LOCAL_REPOSITORIES_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        Self::new(root)
+    }
+
+    pub fn new(root: Option<PathBuf>) -> Self {
+        Self { root }
+    }
+
+    fn repository_path(&self, repository: &Repository) -> Result<PathBuf> {
+        let Some(root) = &self.root else {
+            return Err(anyhow::anyhow!(
+                "Local repositories are disabled, LOCAL_REPOSITORIES_PATH is not set"
+            ));
+        };
+        //Keep the lookup within the root directory and the branch from being read as a git option
+        let is_valid = [&repository.owner, &repository.name]
+            .iter()
+            .all(|segment| is_path_segment(segment))
+            && !repository.branch.starts_with('-');
+        if !is_valid {
+            return Err(anyhow::anyhow!("Invalid local repository"));
+        }
+
+        let path = root.join(&repository.owner).join(&repository.name);
+        if path.is_dir() {
+            Ok(path)
+        } else {
+            Err(anyhow::anyhow!("Local repository not found"))
+        }
+    }
+}
+
+fn is_path_segment(segment: &str) -> bool {
+    !segment.is_empty() && segment != "." && segment != ".." && !segment.contains(['/', '\\'])
+}
+
+fn is_git_repository(path: &Path) -> bool {
+    path.join(".git").exists()
+}
+
+fn git(path: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(args)
+        .output()?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(anyhow::anyhow!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+//Collects the relative and full paths of the files in a directory and its subdirectories
+fn list_directory(root: &Path, directory: &Path, paths: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if entry.file_name() != ".git" {
+                list_directory(root, &path, paths)?;
+            }
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+
+        let relative_path = path
+            .strip_prefix(root)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        paths.push((relative_path, path));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::{PathRules, Provider, SkipReason, SkippedFile};
+
+    fn repository(branch: &str) -> Repository {
+        Repository {
+            owner: "open-sauced".to_string(),
+            name: "fixture".to_string(),
+            branch: branch.to_string(),
+            provider: Provider::Local,
+        }
+    }
+
+    //A fixture repository with a file in a subdirectory, an ignored file, a gitignored file, a generated file and a binary file
+    fn fixture() -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let path = root.join("open-sauced").join("fixture");
+        std::fs::create_dir_all(path.join("src")).unwrap();
+        std::fs::write(path.join("README.md"), "# Fixture\n").unwrap();
+        std::fs::write(path.join("src").join("main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(path.join("Cargo.lock"), "").unwrap();
+        std::fs::write(path.join(".gitignore"), "/local.rs\n").unwrap();
+        std::fs::write(path.join("local.rs"), "").unwrap();
+        std::fs::write(
+            path.join(".gitattributes"),
+            "src/*.pb.rs linguist-generated\n",
+        )
+        .unwrap();
+        std::fs::write(path.join("src").join("api.pb.rs"), "").unwrap();
+        std::fs::write(path.join("logo.png"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        (root, path)
+    }
+
+    fn paths(repository_files: &RepositoryFiles) -> Vec<&str> {
+        let mut paths: Vec<&str> = repository_files
+            .files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[actix_rt::test]
+    async fn test_local_directory() {
+        let (root, _) = fixture();
+        let source = LocalSource::new(Some(root.clone()));
+
+        let repository_files = source
+            .fetch_repo_files(&repository("main"), &PathFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            paths(&repository_files),
+            vec![".gitattributes", ".gitignore", "README.md", "src/main.rs"]
+        );
+        assert!(repository_files.commit_sha.is_none());
+        assert_eq!(
+            repository_files.skipped_files,
+            vec![SkippedFile {
+                path: "logo.png".to_string(),
+                reason: SkipReason::Binary,
+            }]
+        );
+
+        let rules = PathRules {
+            gitignore: false,
+            linguist: Vec::new(),
+            ..Default::default()
+        };
+        let repository_files = source
+            .fetch_repo_files(&repository("main"), &rules.filter().unwrap())
+            .await
+            .unwrap();
+        assert!(paths(&repository_files).contains(&"local.rs"));
+        assert!(paths(&repository_files).contains(&"src/api.pb.rs"));
+
+        let content = source
+            .fetch_file_content(&repository("main"), "main", "src/main.rs")
+            .await
+            .unwrap();
+        assert_eq!(content, "fn main() {}\n");
+        assert!(source
+            .fetch_file_content(&repository("main"), "main", "../fixture/README.md")
+            .await
+            .is_err());
+
+        let mut invalid = repository("main");
+        invalid.owner = "..".to_string();
+        assert!(source
+            .fetch_repo_files(&invalid, &PathFilter::default())
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_local_git_repository() {
+        let git_available = Command::new("git").arg("--version").output().is_ok();
+        if !git_available {
+            return;
+        }
+        let (root, path) = fixture();
+        let commit = |args: &[&str]| {
+            let mut command = Command::new("git");
+            command
+                .arg("-C")
+                .arg(&path)
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args);
+            let output = command.output().unwrap();
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).unwrap().trim().to_string()
+        };
+        commit(&["init", "--quiet"]);
+        commit(&["checkout", "--quiet", "-b", "main"]);
+        commit(&["add", "--all"]);
+        commit(&["commit", "--quiet", "-m", "Initial commit"]);
+        commit(&["tag", "v1"]);
+        let tagged_sha = commit(&["rev-parse", "HEAD"]);
+        std::fs::write(path.join("README.md"), "# Changed\n").unwrap();
+        commit(&["commit", "--quiet", "--all", "-m", "Change the README"]);
+        //Uncommitted changes aren't part of the branch
+        std::fs::write(path.join("uncommitted.rs"), "").unwrap();
+
+        let source = LocalSource::new(Some(root.clone()));
+        let repository_files = source
+            .fetch_repo_files(&repository("main"), &PathFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            paths(&repository_files),
+            vec![".gitattributes", ".gitignore", "README.md", "src/main.rs"]
+        );
+        assert_eq!(
+            repository_files.commit_sha,
+            Some(commit(&["rev-parse", "HEAD"]))
+        );
+
+        let content = source
+            .fetch_file_content(&repository("main"), "main", "README.md")
+            .await
+            .unwrap();
+        assert_eq!(content, "# Changed\n");
+
+        //Tags and commit SHAs are read as they were at the commit
+        let repository_files = source
+            .fetch_repo_files(&repository("v1"), &PathFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(repository_files.commit_sha, Some(tagged_sha.clone()));
+        let content = source
+            .fetch_file_content(&repository("main"), &tagged_sha, "README.md")
+            .await
+            .unwrap();
+        assert_eq!(content, "# Fixture\n");
+        assert!(source
+            .fetch_repo_files(&repository("--output=/tmp/file"), &PathFilter::default())
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+mod bitbucket;
+mod gitea;
+mod github;
+mod gitlab;
+mod local;
+
+use std::io::Read;
+
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{
+    FileReader, GitRules, LicenseFetchResponse, PathFilter, Repository, RepositoryFiles,
+    ALLOWED_LICENSES,
+};
+use crate::prelude::*;
+
+pub use bitbucket::*;
+pub use gitea::*;
+pub use github::*;
+pub use gitlab::*;
+pub use local::*;
+
+//Files checked for the license of repositories on hosts without a license API
+const LICENSE_FILES: &[&str] = &["LICENSE", "LICENSE.md", "LICENSE.txt", "COPYING"];
+
+//Phrases identifying the texts of the allowed licenses, in lowercase with single spaces
+//Licenses that extend the text of another license come first
+const LICENSE_PHRASES: &[(&str, &[&str])] = &[
+    ("apache-2.0", &["apache license", "version 2.0"]),
+    (
+        "unlicense",
+        &["this is free and unencumbered software released into the public domain"],
+    ),
+    ("wtfpl", &["do what the fuck you want to public license"]),
+    (
+        "zlib",
+        &["this notice may not be removed or altered from any source distribution"],
+    ),
+    (
+        "isc",
+        &["permission to use, copy, modify, and/or distribute this software for any purpose with or without fee is hereby granted, provided that"],
+    ),
+    (
+        "0bsd",
+        &["permission to use, copy, modify, and/or distribute this software for any purpose with or without fee is hereby granted"],
+    ),
+    (
+        "bsd-4-clause",
+        &[
+            "redistribution and use in source and binary forms",
+            "all advertising materials mentioning features",
+        ],
+    ),
+    (
+        "bsd-3-clause-clear",
+        &[
+            "redistribution and use in source and binary forms",
+            "no express or implied licenses to any party's patent rights",
+        ],
+    ),
+    (
+        "bsd-3-clause",
+        &[
+            "redistribution and use in source and binary forms",
+            "neither the name",
+        ],
+    ),
+    (
+        "bsd-2-clause",
+        &["redistribution and use in source and binary forms"],
+    ),
+    ("mit", &["permission is hereby granted, free of charge"]),
+];
+
+//Where a repository is fetched from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    GitHub,
+    GitLab,
+    //Forgejo serves the same API as Gitea
+    #[serde(alias = "forgejo")]
+    Gitea,
+    Bitbucket,
+    //A directory or git repository on the server
+    Local,
+}
+
+impl std::fmt::Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Provider::GitHub => write!(f, "github"),
+            Provider::GitLab => write!(f, "gitlab"),
+            Provider::Gitea => write!(f, "gitea"),
+            Provider::Bitbucket => write!(f, "bitbucket"),
+            Provider::Local => write!(f, "local"),
+        }
+    }
+}
+
+impl Provider {
+    //The GitHub token is only used by GitHub, other providers read public repositories
+    pub fn source(&self, github_token: Option<&str>) -> Box<dyn RepositorySource + Send + Sync> {
+        match self {
+            Provider::GitHub => Box::new(GitHubSource::initialize(github_token)),
+            Provider::GitLab => Box::new(GitLabSource::initialize()),
+            Provider::Gitea => Box::new(GiteaSource::initialize()),
+            Provider::Bitbucket => Box::new(BitbucketSource),
+            Provider::Local => Box::new(LocalSource::initialize()),
+        }
+    }
+}
+
+#[async_trait]
+pub trait RepositorySource {
+    //Reads the files of the repository that match the filter
+    async fn fetch_repo_files(
+        &self,
+        repository: &Repository,
+        filter: &PathFilter,
+    ) -> Result<RepositoryFiles>;
+
+    //Reads a file at `git_ref`, a branch, tag or commit SHA
+    async fn fetch_file_content(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+    ) -> Result<String>;
+
+    async fn fetch_license_info(&self, repository: &Repository) -> Result<LicenseFetchResponse>;
+
+    //Whether the repository can be read with the source's credentials
+    //Only sources that read private repositories need to check
+    async fn can_read(&self, _: &Repository) -> Result<bool> {
+        Ok(true)
+    }
+
+    //Link to the lines of a file at `git_ref`, if the source is browsable
+    fn file_url(
+        &self,
+        repository: &Repository,
+        git_ref: &str,
+        path: &str,
+        start_line: usize,
+        end_line: usize,
+    ) -> Option<String>;
+}
+
+//Reads the files of a zip archive with the repository's files in a single top-level directory
+pub fn read_archive(archive: Vec<u8>, filter: &PathFilter) -> Result<RepositoryFiles> {
+    let reader = std::io::Cursor::new(archive);
+    let mut archive = zip::ZipArchive::new(reader)?;
+    //Both GitHub and `git archive` set the commit SHA of the archived tree as the archive comment
+    let commit_sha = parse_commit_sha(archive.comment());
+
+    //The `.gitignore` and `.gitattributes` files are read first, they may come after the files they apply to
+    let rules_files: Vec<(String, String)> = (0..archive.len())
+        .filter_map(|file| {
+            let mut file = archive.by_index(file).ok()?;
+            let file_path = file.name().split_once('/')?.1.to_string();
+            if !file.is_file() || !GitRules::is_rules_file(&file_path) {
+                return None;
+            }
+            let mut content = String::new();
+            file.read_to_string(&mut content).ok()?;
+            Some((file_path, content))
+        })
+        .collect();
+    let git_rules = GitRules::new(&rules_files);
+
+    let mut reader = FileReader::new(filter);
+    for file in 0..archive.len() {
+        let Ok(mut file) = archive.by_index(file) else {
+            continue;
+        };
+        let Some((_, file_path)) = file.name().split_once('/') else {
+            continue;
+        };
+        let file_path = file_path.to_string();
+        if !file.is_file() || !filter.is_indexed(&file_path, &git_rules) {
+            continue;
+        }
+
+        let size = file.size();
+        reader.read(file_path, size, || {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        });
+    }
+    Ok(RepositoryFiles {
+        files: reader.files,
+        commit_sha,
+        private: false,
+        skipped_files: reader.skipped_files,
+    })
+}
+
+//Builds the response for a license key, along with the license's name and URL to show if it's not allowed
+fn license_response(license_key: &str, name: Value, url: Value) -> LicenseFetchResponse {
+    let license_key = license_key.to_lowercase();
+    let permissible: bool = ALLOWED_LICENSES.iter().any(|k| k.eq(&license_key));
+
+    LicenseFetchResponse {
+        permissible,
+        error: if permissible {
+            None
+        } else {
+            Some(json! {{
+                "message": "Impermissible repository license",
+                "license": {
+                    "name": name,
+                    "url": url
+                }
+            }})
+        },
+    }
+}
+
+//Reads the license from the first license file found in the repository
+async fn license_from_file(
+    source: &(dyn RepositorySource + Sync),
+    repository: &Repository,
+) -> Result<LicenseFetchResponse> {
+    for path in LICENSE_FILES {
+        if let Ok(content) = source
+            .fetch_file_content(repository, &repository.branch, path)
+            .await
+        {
+            let license_key = detect_license(&content).unwrap_or_default();
+            return Ok(license_response(license_key, Value::Null, Value::Null));
+        }
+    }
+    Err(anyhow::anyhow!("Unable to fetch repository license"))
+}
+
+//Key of the allowed license whose text is in `content`, if any
+fn detect_license(content: &str) -> Option<&'static str> {
+    let content = content
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase();
+    LICENSE_PHRASES
+        .iter()
+        .find(|(_, phrases)| phrases.iter().all(|phrase| content.contains(phrase)))
+        .map(|(license_key, _)| *license_key)
+}
+
+//Base URL of a self-hosted instance, from the env var `key`
+fn host_url(key: &str, default: &str) -> String {
+    match std::env::var(key) {
+        Ok(url) if !url.is_empty() => url.trim_end_matches('/').to_string(),
+        _ => default.to_string(),
+    }
+}
+
+//Appends the percent-encoded `segments` to the path of `base`
+fn url_with_segments(base: &str, segments: &[&str]) -> Result<Url> {
+    let mut url = Url::parse(base)?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("Invalid base URL {base}"))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+async fn get(url: Url) -> Result<reqwest::Response> {
+    Ok(reqwest::get(url).await?.error_for_status()?)
+}
+
+fn parse_commit_sha(comment: &[u8]) -> Option<String> {
+    let comment = std::str::from_utf8(comment).ok()?.trim();
+    if comment.len() == 40 && comment.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(comment.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commit_sha() {
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(parse_commit_sha(sha.as_bytes()), Some(sha.to_string()));
+        assert_eq!(parse_commit_sha(b""), None);
+        assert_eq!(parse_commit_sha(b"not a commit sha"), None);
+    }
+
+    #[test]
+    fn test_provider_serde() {
+        let repository: Repository =
+            serde_json::from_str(r#"{"owner":"o","name":"n","branch":"b"}"#).unwrap();
+        assert_eq!(repository.provider, Provider::GitHub);
+        assert_eq!(repository.to_string(), "o-n-b");
+
+        let repository: Repository =
+            serde_json::from_str(r#"{"owner":"o","name":"n","branch":"b","provider":"local"}"#)
+                .unwrap();
+        assert_eq!(repository.provider, Provider::Local);
+        assert!(repository.to_string().starts_with("local_"));
+
+        //IDs are unambiguous across providers and fields that contain `-`
+        let repository_id = |provider, owner: &str, name: &str, branch: &str| {
+            Repository {
+                owner: owner.to_string(),
+                name: name.to_string(),
+                branch: branch.to_string(),
+                provider,
+            }
+            .to_string()
+        };
+        assert_ne!(
+            repository_id(Provider::GitHub, "gitlab", "o-n", "b"),
+            repository_id(Provider::GitLab, "o", "n", "b")
+        );
+        assert_ne!(
+            repository_id(Provider::GitLab, "o-n", "b", "c"),
+            repository_id(Provider::GitLab, "o", "n-b", "c")
+        );
+
+        let repository: Repository =
+            serde_json::from_str(r#"{"owner":"o","name":"n","branch":"b","provider":"forgejo"}"#)
+                .unwrap();
+        assert_eq!(repository.provider, Provider::Gitea);
+    }
+
+    #[test]
+    fn test_detect_license() {
+        let mit = "Permission is hereby granted, free of charge, to any person obtaining a copy\nof this software";
+        assert_eq!(detect_license(mit), Some("mit"));
+
+        let bsd = "Redistribution and use in source and binary forms, with or without\nmodification, are permitted";
+        assert_eq!(detect_license(bsd), Some("bsd-2-clause"));
+        let bsd = format!("{bsd}\n3. Neither the name of the copyright holder");
+        assert_eq!(detect_license(&bsd), Some("bsd-3-clause"));
+
+        let apache = "                                 Apache License\n                           Version 2.0, January 2004";
+        assert_eq!(detect_license(apache), Some("apache-2.0"));
+
+        let gpl = "GNU GENERAL PUBLIC LICENSE\nVersion 3, 29 June 2007";
+        assert_eq!(detect_license(gpl), None);
+        assert!(!license_response("", Value::Null, Value::Null).permissible);
+        assert!(license_response("MIT", Value::Null, Value::Null).permissible);
+    }
+
+    #[test]
+    fn test_file_urls() {
+        let repository = |provider| Repository {
+            owner: "group/subgroup".to_string(),
+            name: "repo".to_string(),
+            branch: "main".to_string(),
+            provider,
+        };
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+
+        let url = GitLabSource::new("https://gitlab.example.com/").file_url(
+            &repository(Provider::GitLab),
+            sha,
+            "src/main.rs",
+            1,
+            5,
+        );
+        assert_eq!(
+            url.unwrap(),
+            format!("https://gitlab.example.com/group/subgroup/repo/-/blob/{sha}/src/main.rs#L1-5")
+        );
+
+        let gitea = GiteaSource::new("https://codeberg.org");
+        let url = gitea.file_url(&repository(Provider::Gitea), sha, "src/main.rs", 1, 5);
+        assert_eq!(
+            url.unwrap(),
+            format!("https://codeberg.org/group/subgroup/repo/src/commit/{sha}/src/main.rs#L1-L5")
+        );
+        let url = gitea.file_url(&repository(Provider::Gitea), "main", "src/main.rs", 1, 5);
+        assert_eq!(
+            url.unwrap(),
+            "https://codeberg.org/group/subgroup/repo/src/branch/main/src/main.rs#L1-L5"
+        );
+
+        let url = BitbucketSource.file_url(&repository(Provider::Bitbucket), sha, "a.rs", 2, 3);
+        assert_eq!(
+            url.unwrap(),
+            format!("https://bitbucket.org/group/subgroup/repo/src/{sha}/a.rs#lines-2:3")
+        );
+    }
+}
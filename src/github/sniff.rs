@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{File, PathFilter};
+use crate::constants::{
+    GENERATED_MARKERS, MAX_FILE_BYTES_DEFAULT, MINIFIED_LINE_LENGTH, SKIPPED_FILES_REPORT_LIMIT,
+    SNIFF_BYTES,
+};
+
+//Why the content of a file that the path rules select isn't indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    Binary,
+    NotUtf8,
+    TooLarge,
+    Minified,
+    Generated,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+//Collects the files of a repository to index, along with the ones skipped because of their content
+pub struct FileReader {
+    max_file_bytes: u64,
+    skip_generated: bool,
+    pub files: Vec<File>,
+    pub skipped_files: Vec<SkippedFile>,
+}
+
+impl FileReader {
+    pub fn new(filter: &PathFilter) -> Self {
+        Self {
+            max_file_bytes: max_file_bytes(),
+            skip_generated: filter.skips_generated(),
+            files: Vec::new(),
+            skipped_files: Vec::new(),
+        }
+    }
+
+    //Files over the size limit aren't read, files that fail to be read are left out
+    pub fn read(
+        &mut self,
+        path: String,
+        size: u64,
+        read: impl FnOnce() -> std::io::Result<Vec<u8>>,
+    ) {
+        let content = if size > self.max_file_bytes {
+            Err(SkipReason::TooLarge)
+        } else {
+            let Ok(bytes) = read() else {
+                return;
+            };
+            read_content(&path, bytes, self.skip_generated)
+        };
+        match content {
+            Ok(content) => self.files.push(File {
+                path,
+                length: content.len(),
+                content,
+            }),
+            Err(reason) => self.skipped_files.push(SkippedFile { path, reason }),
+        }
+    }
+}
+
+fn max_file_bytes() -> u64 {
+    match std::env::var("MAX_FILE_BYTES").map(|bytes| bytes.parse()) {
+        Ok(Ok(bytes)) => bytes,
+        _ => MAX_FILE_BYTES_DEFAULT,
+    }
+}
+
+//The content of a file to index, or why it's skipped
+//Generated files are only detected if the path rules skip them
+pub fn read_content(
+    path: &str,
+    bytes: Vec<u8>,
+    skip_generated: bool,
+) -> Result<String, SkipReason> {
+    //Git's heuristic, text files don't have NUL bytes in their first few KBs
+    if bytes.iter().take(SNIFF_BYTES).any(|byte| *byte == 0) {
+        return Err(SkipReason::Binary);
+    }
+    let content = String::from_utf8(bytes).map_err(|_| SkipReason::NotUtf8)?;
+    if is_minified(path, &content) {
+        return Err(SkipReason::Minified);
+    }
+    if skip_generated && is_generated(&content) {
+        return Err(SkipReason::Generated);
+    }
+    Ok(content)
+}
+
+//Minified files are named so by convention, or have lines far longer than hand-written code
+fn is_minified(path: &str, content: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or_default();
+    if file_name.contains(".min.") {
+        return true;
+    }
+    let line_count = content.lines().count().max(1);
+    content.len() / line_count > MINIFIED_LINE_LENGTH
+}
+
+//Code generators mark their output in a header comment, e.g. `// Code generated by protoc-gen-go. DO NOT EDIT.`
+fn is_generated(content: &str) -> bool {
+    content
+        .lines()
+        .take(5)
+        .any(|line| GENERATED_MARKERS.iter().any(|marker| line.contains(marker)))
+}
+
+//The number of skipped files for each reason and the first `SKIPPED_FILES_REPORT_LIMIT` of them
+pub fn skipped_files_report(skipped_files: &[SkippedFile]) -> Value {
+    let mut reasons: BTreeMap<SkipReason, usize> = BTreeMap::new();
+    for file in skipped_files {
+        *reasons.entry(file.reason).or_default() += 1;
+    }
+    json!({
+        "count": skipped_files.len(),
+        "reasons": reasons,
+        "files": skipped_files.iter().take(SKIPPED_FILES_REPORT_LIMIT).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_content() {
+        let source = "fn main() {\n    println!(\"Hello\");\n}\n";
+        assert_eq!(
+            read_content("src/main.rs", source.as_bytes().to_vec(), true).unwrap(),
+            source
+        );
+
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        assert_eq!(read_content("logo.png", png, true), Err(SkipReason::Binary));
+        let wasm = b"\0asm\x01\0\0\0".to_vec();
+        assert_eq!(
+            read_content("app.wasm", wasm, true),
+            Err(SkipReason::Binary)
+        );
+        let latin1 = b"caf\xe9\n".to_vec();
+        assert_eq!(
+            read_content("menu.txt", latin1, true),
+            Err(SkipReason::NotUtf8)
+        );
+
+        let bundle = format!("var a={};", "1,".repeat(5000));
+        assert_eq!(
+            read_content("dist.js", bundle.into_bytes(), true),
+            Err(SkipReason::Minified)
+        );
+        assert_eq!(
+            read_content("jquery.min.js", b"var a;\n".to_vec(), true),
+            Err(SkipReason::Minified)
+        );
+
+        let generated = "// Code generated by protoc-gen-go. DO NOT EDIT.\npackage api\n";
+        assert_eq!(
+            read_content("api.pb.go", generated.as_bytes().to_vec(), true),
+            Err(SkipReason::Generated)
+        );
+        assert!(read_content("api.pb.go", generated.as_bytes().to_vec(), false).is_ok());
+        //Markers past the header are part of the code
+        let marker = format!("{}// DO NOT EDIT\n", "let a = 1;\n".repeat(10));
+        assert!(read_content("src/lib.rs", marker.into_bytes(), true).is_ok());
+    }
+
+    #[test]
+    fn test_skipped_files_report() {
+        let skipped_files: Vec<SkippedFile> = (0..SKIPPED_FILES_REPORT_LIMIT + 1)
+            .map(|index| SkippedFile {
+                path: format!("assets/{index}.png"),
+                reason: SkipReason::Binary,
+            })
+            .chain([SkippedFile {
+                path: "fixtures/data.json".to_string(),
+                reason: SkipReason::TooLarge,
+            }])
+            .collect();
+
+        let report = skipped_files_report(&skipped_files);
+        assert_eq!(report["count"], SKIPPED_FILES_REPORT_LIMIT + 2);
+        assert_eq!(report["reasons"]["binary"], SKIPPED_FILES_REPORT_LIMIT + 1);
+        assert_eq!(report["reasons"]["too_large"], 1);
+        assert_eq!(
+            report["files"].as_array().unwrap().len(),
+            SKIPPED_FILES_REPORT_LIMIT
+        );
+        assert_eq!(
+            report["files"][0],
+            json!({ "path": "assets/0.png", "reason": "binary" })
+        );
+    }
+}
@@ -0,0 +1,295 @@
+#![allow(unused_must_use)]
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web_lab::sse::Data;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::{watch, Semaphore};
+
+use crate::{
+    constants::{EMBED_JOBS_CONCURRENCY, EMBED_JOB_TTL_SECS},
+    github::{PathRules, Repository},
+    prelude::*,
+    routes::events::{emit, EmbedEvent, EventRecord, EventSender},
+    utils::errors::{RequestError, RequestErrorKind},
+};
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct JobStatus {
+    pub id: String,
+    pub repository: Repository,
+    #[serde(skip)]
+    pub rules: PathRules,
+    //Whether the job was submitted with a per-request token, which may read private repositories
+    #[serde(skip)]
+    pub token_scoped: bool,
+    //Name of the latest event emitted by the job, "QUEUED" until the job starts
+    pub phase: &'static str,
+    //Data of the latest event that carried any, e.g. the number of files being embedded
+    pub progress: Option<Value>,
+    pub error: Option<String>,
+    //Kind of the error, None for errors of unknown kind
+    #[serde(skip)]
+    pub error_kind: Option<RequestErrorKind>,
+    //Seconds since the Unix epoch
+    pub updated_at: u64,
+}
+
+impl JobStatus {
+    fn new(id: String, repository: Repository, rules: PathRules, token_scoped: bool) -> Self {
+        Self {
+            id,
+            repository,
+            rules,
+            token_scoped,
+            phase: "QUEUED",
+            progress: None,
+            error: None,
+            error_kind: None,
+            updated_at: now(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.phase, "DONE" | "ERROR")
+    }
+
+    pub fn update(&mut self, record: EventRecord) {
+        let EventRecord { event, data } = record;
+        self.phase = event;
+        self.updated_at = now();
+        if event == "ERROR" {
+            self.error = data.map(|data| match data {
+                Value::String(error) => error,
+                data => data.to_string(),
+            });
+        } else if data.is_some() {
+            self.progress = data;
+        }
+    }
+}
+
+impl From<JobStatus> for Data {
+    fn from(status: JobStatus) -> Data {
+        Data::new(json!(status).to_string()).event(status.phase)
+    }
+}
+
+impl From<JobStatus> for EventRecord {
+    fn from(status: JobStatus) -> EventRecord {
+        EventRecord {
+            event: status.phase,
+            data: Some(json!(status)),
+        }
+    }
+}
+
+//Queue of embedding jobs running in the background
+//At most one job per repository is in-flight at a time, finished jobs are kept for `EMBED_JOB_TTL_SECS`
+pub struct EmbedJobs {
+    jobs: Mutex<HashMap<String, watch::Receiver<JobStatus>>>,
+    permits: Arc<Semaphore>,
+}
+
+impl Default for EmbedJobs {
+    fn default() -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            permits: Arc::new(Semaphore::new(EMBED_JOBS_CONCURRENCY)),
+        }
+    }
+}
+
+impl EmbedJobs {
+    //Queues a job for the repository and returns its ID
+    //If a job for the same repository and rules is already in-flight, its ID is returned instead
+    //If the in-flight job has different rules, its ID is returned as an error, since both jobs would write the same collection
+    pub fn submit<F, Fut>(
+        &self,
+        repository: Repository,
+        rules: PathRules,
+        token_scoped: bool,
+        run: F,
+    ) -> std::result::Result<String, String>
+    where
+        F: FnOnce(EventSender) -> Fut + 'static,
+        Fut: Future<Output = Result<()>> + 'static,
+    {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| {
+            !is_finished(job) || now() - job.borrow().updated_at < EMBED_JOB_TTL_SECS
+        });
+
+        let in_flight = jobs
+            .values()
+            .find(|job| job.borrow().repository == repository && !is_finished(job));
+        if let Some(job) = in_flight {
+            let job = job.borrow();
+            if job.rules == rules {
+                return Ok(job.id.clone());
+            }
+            return Err(job.id.clone());
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let (sender, receiver) =
+            watch::channel(JobStatus::new(id.clone(), repository, rules, token_scoped));
+        jobs.insert(id.clone(), receiver);
+        drop(jobs);
+
+        let permits = self.permits.clone();
+        actix_rt::spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let status = Arc::new(sender);
+            let sender = EventSender::Job(status.clone());
+            //The job runs in its own task, so that a panic is reported as an error
+            let (error, kind) = match actix_rt::spawn(run(sender.clone())).await {
+                Ok(Ok(())) => return,
+                Ok(Err(e)) => (e.to_string(), RequestError::kind(&e)),
+                Err(_) => ("The job stopped unexpectedly".to_string(), None),
+            };
+            eprintln!("/embed error: {}", error);
+            status.send_modify(|status| status.error_kind = kind);
+            emit(&sender, EmbedEvent::Error(Some(error.into()))).await;
+        });
+
+        Ok(id)
+    }
+
+    //Whether a job for the repository is queued or running
+    pub fn is_in_flight(&self, repository: &Repository) -> bool {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .any(|job| &job.borrow().repository == repository && !is_finished(job))
+    }
+
+    pub fn get(&self, id: &str) -> Option<watch::Receiver<JobStatus>> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+}
+
+//Jobs whose sender was dropped without finishing can't make progress anymore
+fn is_finished(job: &watch::Receiver<JobStatus>) -> bool {
+    job.has_changed().is_err() || job.borrow().is_finished()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Provider;
+
+    fn repository() -> Repository {
+        Repository {
+            owner: "open-sauced".to_string(),
+            name: "ai".to_string(),
+            branch: "beta".to_string(),
+            provider: Provider::GitHub,
+        }
+    }
+
+    #[test]
+    fn test_job_status_update() {
+        let mut status =
+            JobStatus::new("id".to_string(), repository(), PathRules::default(), false);
+        assert!(!status.is_finished());
+
+        status.update(EmbedEvent::EmbedRepo(Some(json!({ "files": 3 }))).into());
+        assert_eq!(status.phase, "EMBED_REPO");
+        assert_eq!(status.progress, Some(json!({ "files": 3 })));
+
+        status.update(EmbedEvent::Error(Some("Unable to fetch".into())).into());
+        assert_eq!(status.phase, "ERROR");
+        assert_eq!(status.progress, Some(json!({ "files": 3 })));
+        assert_eq!(status.error, Some("Unable to fetch".to_string()));
+        assert!(status.is_finished());
+    }
+
+    #[actix_rt::test]
+    async fn test_submit_deduplicates_in_flight_jobs() {
+        let jobs = EmbedJobs::default();
+        let (release, wait) = tokio::sync::oneshot::channel::<()>();
+
+        let id = jobs
+            .submit(
+                repository(),
+                PathRules::default(),
+                false,
+                |sender| async move {
+                    emit(&sender, EmbedEvent::FetchRepo(None)).await;
+                    wait.await?;
+                    emit(&sender, EmbedEvent::Done(None)).await;
+                    Ok(())
+                },
+            )
+            .unwrap();
+        let duplicate_id = jobs
+            .submit(repository(), PathRules::default(), false, |_| async {
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(id, duplicate_id);
+        let other_rules = PathRules {
+            include: vec!["src/**".to_string()],
+            ..Default::default()
+        };
+        let conflict = jobs.submit(repository(), other_rules, false, |_| async { Ok(()) });
+        assert_eq!(conflict, Err(id.clone()));
+        assert!(jobs.is_in_flight(&repository()));
+
+        let mut status = jobs.get(&id).unwrap();
+        release.send(()).unwrap();
+        status.wait_for(JobStatus::is_finished).await.unwrap();
+        assert_eq!(status.borrow().phase, "DONE");
+        assert!(!jobs.is_in_flight(&repository()));
+
+        let next_id = jobs
+            .submit(repository(), PathRules::default(), false, |_| async {
+                Ok(())
+            })
+            .unwrap();
+        assert_ne!(id, next_id);
+    }
+
+    #[actix_rt::test]
+    async fn test_panicked_job_is_finished() {
+        let jobs = EmbedJobs::default();
+        let id = jobs
+            .submit(
+                repository(),
+                PathRules::default(),
+                false,
+                |sender| async move {
+                    emit(&sender, EmbedEvent::FetchRepo(None)).await;
+                    panic!("Unexpected archive");
+                },
+            )
+            .unwrap();
+
+        let mut status = jobs.get(&id).unwrap();
+        status.wait_for(JobStatus::is_finished).await.unwrap();
+        assert_eq!(status.borrow().phase, "ERROR");
+        assert_eq!(
+            status.borrow().error,
+            Some("The job stopped unexpectedly".to_string())
+        );
+
+        let next_id = jobs
+            .submit(repository(), PathRules::default(), false, |_| async {
+                Ok(())
+            })
+            .unwrap();
+        assert_ne!(id, next_id);
+    }
+}
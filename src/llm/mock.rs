@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use openai_api_rs::v1::chat_completion::{ChatCompletionRequest, FunctionCall};
+use serde_json::Value;
+
+use super::{ChatBackend, ChatCompletion, CompletionStream};
+use crate::{prelude::*, utils::functions::Function};
+
+//Replays a scripted sequence of completions, one per request, for deterministic tests
+//Streamed completions are split into a delta per word
+pub struct MockBackend {
+    script: Mutex<VecDeque<ChatCompletion>>,
+}
+
+impl MockBackend {
+    pub fn new(script: Vec<ChatCompletion>) -> Self {
+        Self {
+            script: Mutex::new(script.into()),
+        }
+    }
+
+    pub fn function_call(function: Function, arguments: Value) -> ChatCompletion {
+        ChatCompletion::FunctionCall(FunctionCall {
+            name: Some(function.to_string()),
+            arguments: Some(arguments.to_string()),
+        })
+    }
+
+    pub fn message(content: &str) -> ChatCompletion {
+        ChatCompletion::Message(content.to_string())
+    }
+
+    fn next(&self) -> Result<ChatCompletion> {
+        self.script
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("Mock script exhausted"))
+    }
+}
+
+#[async_trait]
+impl ChatBackend for MockBackend {
+    async fn chat_completion(&self, _: ChatCompletionRequest) -> Result<ChatCompletion> {
+        self.next()
+    }
+
+    async fn stream_chat_completion(
+        &self,
+        _: ChatCompletionRequest,
+    ) -> Result<Box<dyn CompletionStream + Send>> {
+        match self.next()? {
+            ChatCompletion::Message(content) => Ok(Box::new(MockStream {
+                deltas: content.split_inclusive(' ').map(str::to_string).collect(),
+            })),
+            ChatCompletion::FunctionCall(_) => {
+                Err(anyhow::anyhow!("Function calls can't be streamed"))
+            }
+        }
+    }
+}
+
+struct MockStream {
+    deltas: VecDeque<String>,
+}
+
+#[async_trait]
+impl CompletionStream for MockStream {
+    async fn next_delta(&mut self) -> Result<Option<String>> {
+        Ok(self.deltas.pop_front())
+    }
+}
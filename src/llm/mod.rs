@@ -0,0 +1,33 @@
+#[cfg(test)]
+pub mod mock;
+mod openai;
+mod stream;
+
+use crate::prelude::*;
+use async_trait::async_trait;
+use openai_api_rs::v1::chat_completion::{ChatCompletionRequest, FunctionCall};
+
+pub use openai::*;
+
+//The reply of the model to a chat completion request
+#[derive(Debug, Clone)]
+pub enum ChatCompletion {
+    FunctionCall(FunctionCall),
+    Message(String),
+}
+
+#[async_trait]
+pub trait ChatBackend {
+    async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletion>;
+
+    async fn stream_chat_completion(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<Box<dyn CompletionStream + Send>>;
+}
+
+#[async_trait]
+pub trait CompletionStream {
+    //Returns the next content delta, or None once the completion has finished
+    async fn next_delta(&mut self) -> Result<Option<String>>;
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use openai_api_rs::v1::chat_completion::{
+    ChatCompletionRequest, ChatCompletionResponse, FinishReason,
+};
+
+use super::{stream::OpenAIStream, ChatBackend, ChatCompletion, CompletionStream};
+use crate::{
+    constants::{CHAT_COMPLETION_MODEL, CHAT_COMPLETION_TIMEOUT_SECS, OPENAI_API_BASE_DEFAULT},
+    prelude::*,
+};
+
+//Chat completions from the OpenAI API or any server that implements it, e.g. llama.cpp, vLLM or Ollama
+pub struct OpenAIBackend {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+}
+
+#[async_trait]
+impl ChatBackend for OpenAIBackend {
+    async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletion> {
+        let response = self
+            .send(request)
+            .await?
+            .json::<ChatCompletionResponse>()
+            .await?;
+        let Some(choice) = response.choices.into_iter().next() else {
+            return Err(anyhow::anyhow!("Model returned no choices."));
+        };
+
+        //Some servers finish a function call with `stop`, so the function call is checked first
+        if let Some(function_call) = choice.message.function_call {
+            return Ok(ChatCompletion::FunctionCall(function_call));
+        }
+        match choice.finish_reason {
+            Some(FinishReason::length) => Err(anyhow::anyhow!(
+                "Model response was cut off by the token limit."
+            )),
+            Some(FinishReason::content_filter) => Err(anyhow::anyhow!(
+                "Model response was blocked by the content filter."
+            )),
+            Some(FinishReason::function_call) => Err(anyhow::anyhow!(
+                "Model finished with a function call but returned none."
+            )),
+            _ => Ok(ChatCompletion::Message(
+                choice.message.content.unwrap_or_default(),
+            )),
+        }
+    }
+
+    async fn stream_chat_completion(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<Box<dyn CompletionStream + Send>> {
+        let response = self.send(request.stream(true)).await?;
+        Ok(Box::new(OpenAIStream::new(response)))
+    }
+}
+
+impl OpenAIBackend {
+    async fn send(&self, mut request: ChatCompletionRequest) -> Result<reqwest::Response> {
+        request.model = self.model.clone();
+
+        let mut builder = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&request);
+        //Self-hosted servers may not require an API key
+        if !self.api_key.is_empty() {
+            builder = builder.bearer_auth(&self.api_key);
+        }
+        let response = builder.send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("{}: {}", status, message));
+        }
+        Ok(response)
+    }
+
+    pub fn initialize() -> Result<OpenAIBackend> {
+        let base_url = env_or_default("OPENAI_API_BASE", OPENAI_API_BASE_DEFAULT);
+        let model = env_or_default("OPENAI_MODEL", CHAT_COMPLETION_MODEL);
+        let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
+
+        Ok(OpenAIBackend {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(CHAT_COMPLETION_TIMEOUT_SECS))
+                .build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+        })
+    }
+}
+
+fn env_or_default(key: &str, default: &str) -> String {
+    match std::env::var(key) {
+        Ok(value) if !value.is_empty() => value,
+        _ => default.to_string(),
+    }
+}
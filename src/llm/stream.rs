@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::CompletionStream;
+use crate::prelude::*;
+
+//Streamed chat completion of an OpenAI compatible API
+//Reference: https://platform.openai.com/docs/api-reference/chat/streaming
+pub struct OpenAIStream {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    is_done: bool,
+}
+
+impl OpenAIStream {
+    pub fn new(response: reqwest::Response) -> Self {
+        Self {
+            response,
+            buffer: Vec::new(),
+            is_done: false,
+        }
+    }
+}
+
+#[async_trait]
+impl CompletionStream for OpenAIStream {
+    async fn next_delta(&mut self) -> Result<Option<String>> {
+        loop {
+            while let Some(index) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=index).collect();
+                match parse_line(&String::from_utf8_lossy(&line))? {
+                    StreamLine::Delta(delta) => return Ok(Some(delta)),
+                    StreamLine::Done => self.is_done = true,
+                    StreamLine::Empty => {}
+                }
+            }
+
+            if self.is_done {
+                return Ok(None);
+            }
+
+            match self.response.chunk().await? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                None => {
+                    //Process any trailing line without a line break
+                    if self.buffer.is_empty() {
+                        return Ok(None);
+                    }
+                    self.buffer.push(b'\n');
+                    self.is_done = true;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum StreamLine {
+    Delta(String),
+    Done,
+    Empty,
+}
+
+fn parse_line(line: &str) -> Result<StreamLine> {
+    let Some(data) = line.trim().strip_prefix("data:") else {
+        return Ok(StreamLine::Empty);
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return Ok(StreamLine::Done);
+    }
+
+    let chunk = serde_json::from_str::<Value>(data)?;
+    match chunk["choices"][0]["delta"]["content"].as_str() {
+        Some(delta) if !delta.is_empty() => Ok(StreamLine::Delta(delta.to_string())),
+        _ => Ok(StreamLine::Empty),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let line =
+            r#"data: {"choices":[{"index":0,"delta":{"content":"Hello"},"finish_reason":null}]}"#;
+        assert_eq!(
+            parse_line(line).unwrap(),
+            StreamLine::Delta("Hello".to_string())
+        );
+
+        let line = r#"data: {"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#;
+        assert_eq!(parse_line(line).unwrap(), StreamLine::Empty);
+
+        assert_eq!(parse_line("data: [DONE]").unwrap(), StreamLine::Done);
+        assert_eq!(parse_line("").unwrap(), StreamLine::Empty);
+        assert!(parse_line("data: {invalid").is_err());
+    }
+}
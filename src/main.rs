@@ -1,8 +1,12 @@
+mod chunker;
 mod constants;
+mod contents;
 mod conversation;
 mod db;
 mod embeddings;
 mod github;
+mod jobs;
+mod llm;
 mod prelude;
 mod routes;
 mod utils;
@@ -21,7 +25,14 @@ async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(Env::default().default_filter_or("info"));
 
     let model: Arc<embeddings::Fastembed> = Arc::new(embeddings::Fastembed::try_new().unwrap());
-    let db: Arc<db::QdrantDB> = Arc::new(db::QdrantDB::initialize().unwrap());
+    let db: Arc<db::DynRepositoryEmbeddingsDB> = db::initialize().unwrap();
+    let chat: Arc<llm::OpenAIBackend> = Arc::new(llm::OpenAIBackend::initialize().unwrap());
+    let contents: Arc<contents::ContentStore> =
+        Arc::new(contents::ContentStore::initialize().unwrap());
+    let jobs: Arc<jobs::EmbedJobs> = Arc::new(jobs::EmbedJobs::default());
+    let conversations: Arc<conversation::ConversationStore> =
+        Arc::new(conversation::ConversationStore::default());
+    let access: Arc<github::AccessCache> = Arc::new(github::AccessCache::default());
 
     let mut port = std::env::var("WEBSERVER_PORT").unwrap_or(WEBSERVER_PORT_DEFAULT.into());
     if port.is_empty() {
@@ -37,8 +48,16 @@ async fn main() -> std::io::Result<()> {
             .service(routes::embeddings)
             .service(routes::query)
             .service(routes::repo)
+            .service(routes::delete_repo)
+            .service(routes::repos)
+            .service(routes::job)
             .app_data(web::Data::new(model.clone()))
             .app_data(web::Data::new(db.clone()))
+            .app_data(web::Data::new(chat.clone()))
+            .app_data(web::Data::new(contents.clone()))
+            .app_data(web::Data::new(conversations.clone()))
+            .app_data(web::Data::new(jobs.clone()))
+            .app_data(web::Data::new(access.clone()))
     })
     .bind(("0.0.0.0", port))?
     .run()
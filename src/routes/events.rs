@@ -1,13 +1,60 @@
+use std::sync::{Arc, Mutex};
+
 use actix_web_lab::sse::{Data, SendError, Sender};
+use serde_json::Value;
+use tokio::sync::watch;
+
+use crate::{jobs::JobStatus, sse_events};
+
+//Destination of the events emitted while handling a request
+//Events are either streamed to the client as SSEs, collected to build a single JSON response
+//or used to update the status of a background job
+#[derive(Debug, Clone)]
+pub enum EventSender {
+    Sse(Sender),
+    Collector(Arc<Mutex<Vec<EventRecord>>>),
+    Job(Arc<watch::Sender<JobStatus>>),
+}
 
-use crate::sse_events;
+impl EventSender {
+    pub fn collector() -> Self {
+        EventSender::Collector(Arc::new(Mutex::new(Vec::new())))
+    }
+
+    //Returns the events collected so far. Always empty for other senders
+    pub fn collected(&self) -> Vec<EventRecord> {
+        match self {
+            EventSender::Sse(_) | EventSender::Job(_) => Vec::new(),
+            EventSender::Collector(events) => events.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRecord {
+    pub event: &'static str,
+    pub data: Option<Value>,
+}
 
-pub async fn emit<T: Into<Data>>(sender: &Sender, event: T) -> Result<(), SendError> {
-    sender.send(event.into()).await?;
-    //Empty message to force send the above message to receiver
-    //Else, will stay in the buffer when using actix_rt::spawn
-    //TODO: Investigate further to avoid this workaround
-    sender.send(Data::new("")).await?;
+pub async fn emit<T: Into<Data> + Into<EventRecord>>(
+    sender: &EventSender,
+    event: T,
+) -> Result<(), SendError> {
+    match sender {
+        EventSender::Sse(sender) => {
+            sender.send(Into::<Data>::into(event)).await?;
+            //Empty message to force send the above message to receiver
+            //Else, will stay in the buffer when using actix_rt::spawn
+            //TODO: Investigate further to avoid this workaround
+            sender.send(Data::new("")).await?;
+        }
+        EventSender::Collector(events) => {
+            events.lock().unwrap().push(event.into());
+        }
+        EventSender::Job(status) => {
+            status.send_modify(|status| status.update(event.into()));
+        }
+    }
     Ok(())
 }
 
@@ -27,6 +74,7 @@ sse_events! {
     (SearchFile, "SEARCH_FILE"),
     (SearchPath, "SEARCH_PATH"),
     (GenerateResponse, "GENERATE_RESPONSE"),
+    (AnswerDelta, "ANSWER_DELTA"),
     (Done, "DONE"),
     (Error, "ERROR"),
 }
@@ -1,111 +1,417 @@
 #![allow(unused_must_use)]
 pub mod events;
-use crate::constants::SSE_CHANNEL_BUFFER_SIZE;
+mod responses;
+use crate::constants::{ADMIN_TOKEN_HEADER, GITHUB_TOKEN_HEADER, SSE_CHANNEL_BUFFER_SIZE};
 
-use crate::conversation::{Conversation, Query};
-use crate::github::{fetch_license_info, fetch_repo_files};
+use crate::conversation::{Conversation, ConversationSession, ConversationStore, Query};
+use crate::github::{diff_repo_files, fetch_license_info, fetch_repo_files, skipped_files_report};
+use crate::github::{AccessCache, IndexSettings, PathRules, Repository, RepositoryMetadata};
+use crate::jobs::{EmbedJobs, JobStatus};
 use crate::routes::events::QueryEvent;
-use crate::{db::RepositoryEmbeddingsDB, github::Repository};
+use crate::utils::errors::{RequestError, RequestErrorKind};
+use actix_web::http::{
+    header::{Accept, LOCATION},
+    StatusCode,
+};
 use actix_web::web::Query as ActixQuery;
-use actix_web::HttpResponse;
 use actix_web::{
-    error::{ErrorBadRequest, ErrorForbidden, ErrorNotFound},
+    delete,
+    error::{
+        ErrorBadRequest, ErrorConflict, ErrorForbidden, ErrorInternalServerError, ErrorNotFound,
+        ErrorUnauthorized,
+    },
     get, post,
-    web::{self, Json},
-    Responder, Result,
+    web::{self, Header, Json},
+    Either, Responder, Result,
 };
+use actix_web::{HttpRequest, HttpResponse};
 use actix_web_lab::sse;
+use serde::Deserialize;
 use serde_json::json;
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::{db::QdrantDB, embeddings::Fastembed, github::embed_repo};
-use events::{emit, EmbedEvent};
+use crate::{
+    contents::ContentStore,
+    db::DynRepositoryEmbeddingsDB,
+    embeddings::{EmbeddingsModel, Fastembed},
+    github::embed_repo,
+    llm::OpenAIBackend,
+};
+use events::{emit, EmbedEvent, EventSender};
+use responses::{EmbedResponse, QueryResponse};
+
+#[derive(Deserialize)]
+struct EmbedRequest {
+    #[serde(flatten)]
+    repository: Repository,
+    #[serde(flatten)]
+    rules: PathRules,
+}
 
 #[post("/embed")]
 async fn embeddings(
-    data: Json<Repository>,
-    db: web::Data<Arc<QdrantDB>>,
+    request: HttpRequest,
+    data: Json<EmbedRequest>,
+    accept: Option<Header<Accept>>,
+    db: web::Data<Arc<DynRepositoryEmbeddingsDB>>,
     model: web::Data<Arc<Fastembed>>,
+    contents: web::Data<Arc<ContentStore>>,
+    jobs: web::Data<Arc<EmbedJobs>>,
 ) -> Result<impl Responder> {
-    let license_info = fetch_license_info(&data).await.map_err(ErrorBadRequest)?;
+    let EmbedRequest { repository, rules } = data.into_inner();
+    //Invalid globs are rejected before the job is queued
+    rules.filter().map_err(ErrorBadRequest)?;
+
+    let github_token = github_token(&request);
+    let license_info = fetch_license_info(&repository, github_token.as_deref())
+        .await
+        .map_err(ErrorBadRequest)?;
     if !license_info.permissible {
         return Err(ErrorForbidden(license_info.error.unwrap_or_default()));
     }
 
-    let (sender, rx) = sse::channel(SSE_CHANNEL_BUFFER_SIZE);
-
-    actix_rt::spawn(async move {
-        let handle_embed = async {
-            let repository = data.into_inner();
-
-            emit(&sender, EmbedEvent::FetchRepo(None)).await;
-            let files = fetch_repo_files(&repository).await?;
+    let db = db.get_ref().clone();
+    let model = model.get_ref().clone();
+    let contents = contents.get_ref().clone();
 
-            emit(
+    let job_id = jobs.submit(
+        repository.clone(),
+        rules.clone(),
+        github_token.is_some(),
+        move |sender| async move {
+            handle_embed(
+                &repository,
+                &rules,
+                github_token.as_deref(),
+                db.as_ref(),
+                model.as_ref(),
+                contents.as_ref(),
                 &sender,
-                EmbedEvent::EmbedRepo(Some(json!({
-                    "files": files.len(),
-                }))),
             )
-            .await;
-            let embeddings = embed_repo(&repository, files, model.get_ref().as_ref()).await?;
+            .await
+        },
+    );
+    let job_id = job_id.map_err(|job_id| {
+        ErrorConflict(format!(
+            "Job {job_id} is already embedding the repository with different rules"
+        ))
+    })?;
 
-            emit(&sender, EmbedEvent::SaveEmbeddings(None)).await;
-            db.get_ref().insert_repo_embeddings(embeddings).await?;
+    if prefers_json(accept) {
+        return embed_response(&jobs, &job_id).await;
+    }
+    Ok(HttpResponse::Accepted()
+        .insert_header((LOCATION, format!("/jobs/{job_id}")))
+        .json(json!({ "job_id": job_id })))
+}
 
-            emit(&sender, EmbedEvent::Done(None)).await;
-            Ok::<(), anyhow::Error>(())
-        };
+//Waits for the job to finish and responds with a summary of its outcome
+async fn embed_response(jobs: &EmbedJobs, job_id: &str) -> Result<HttpResponse> {
+    let mut status = jobs
+        .get(job_id)
+        .ok_or_else(|| ErrorNotFound("Job not found"))?;
+    //Finished jobs always end with DONE or ERROR, even if they panicked
+    status.wait_for(JobStatus::is_finished).await.ok();
+    let status = status.borrow().clone();
+    let status_code = match status.error {
+        Some(_) => error_status(status.error_kind),
+        None => StatusCode::OK,
+    };
+    Ok(HttpResponse::build(status_code).json(EmbedResponse::from(status)))
+}
 
-        if let Err(e) = handle_embed.await {
-            eprintln!("/embed error: {}", e);
-            emit(&sender, EmbedEvent::Error(Some(e.to_string().into()))).await;
+async fn handle_embed(
+    repository: &Repository,
+    rules: &PathRules,
+    github_token: Option<&str>,
+    db: &DynRepositoryEmbeddingsDB,
+    model: &Fastembed,
+    contents: &ContentStore,
+    sender: &EventSender,
+) -> anyhow::Result<()> {
+    emit(sender, EmbedEvent::FetchRepo(None)).await;
+    let mut repository_files = fetch_repo_files(repository, &rules.filter()?, github_token)
+        .await
+        .map_err(RequestError::upstream)?;
+    //Files can only be served from a snapshot of a known commit
+    let commit_sha = repository_files.commit_sha.clone();
+    if let Some(commit_sha) = commit_sha.clone() {
+        let contents = contents.clone();
+        let repository = repository.clone();
+        let files = std::mem::take(&mut repository_files.files);
+        repository_files.files = actix_rt::task::spawn_blocking(move || {
+            contents.save(&repository, &commit_sha, &files)?;
+            Ok::<_, anyhow::Error>(files)
+        })
+        .await??;
+    }
+
+    //Re-index incrementally if the repository was indexed with the same model, chunking and indexes
+    //Files that the rules no longer select are removed like deleted files
+    let index_settings = IndexSettings::new(model.chunk_sizer());
+    let indexed_hashes = match db.get_repo_metadata(repository).await? {
+        Some(metadata)
+            if metadata.embedding_model == model.model_name()
+                && metadata.index == index_settings =>
+        {
+            if metadata.commit_sha.is_some()
+                && metadata.commit_sha == repository_files.commit_sha
+                && &metadata.rules == rules
+            {
+                emit(sender, EmbedEvent::Done(None)).await;
+                return Ok(());
+            }
+            db.get_file_hashes(repository).await?
         }
-    });
+        _ => HashMap::new(),
+    };
+    let is_incremental = !indexed_hashes.is_empty();
+    let skipped_files = skipped_files_report(&repository_files.skipped_files);
+    let repository_changes = diff_repo_files(repository_files, &indexed_hashes);
 
-    Ok(rx)
+    emit(
+        sender,
+        EmbedEvent::EmbedRepo(Some(json!({
+            "files": repository_changes.files.len(),
+            "deleted_files": repository_changes.deleted_paths.len(),
+            "commit_sha": repository_changes.commit_sha,
+            "skipped_files": skipped_files,
+        }))),
+    )
+    .await;
+    let repository_embeddings = embed_repo(repository, rules, repository_changes, model).await?;
+
+    emit(sender, EmbedEvent::SaveEmbeddings(None)).await;
+    if is_incremental {
+        db.update_repo_embeddings(repository_embeddings).await?;
+    } else {
+        db.insert_repo_embeddings(repository_embeddings).await?;
+    }
+    //Snapshots of previously indexed commits are no longer used by queries
+    if let Some(commit_sha) = commit_sha {
+        let contents = contents.clone();
+        let repository = repository.clone();
+        actix_rt::task::spawn_blocking(move || contents.prune(&repository, &commit_sha)).await??;
+    }
+
+    emit(sender, EmbedEvent::Done(None)).await;
+    Ok(())
 }
 
 #[post("/query")]
+#[allow(clippy::too_many_arguments)]
 async fn query(
+    request: HttpRequest,
     data: Json<Query>,
-    db: web::Data<Arc<QdrantDB>>,
+    accept: Option<Header<Accept>>,
+    db: web::Data<Arc<DynRepositoryEmbeddingsDB>>,
     model: web::Data<Arc<Fastembed>>,
+    chat: web::Data<Arc<OpenAIBackend>>,
+    contents: web::Data<Arc<ContentStore>>,
+    conversations: web::Data<Arc<ConversationStore>>,
+    access: web::Data<Arc<AccessCache>>,
 ) -> Result<impl Responder> {
-    if db.is_indexed(&data.repository).await.unwrap_or_default() {
+    let github_token = github_token(&request);
+    if is_visible(
+        db.get_ref().as_ref(),
+        access.get_ref(),
+        &data.repository,
+        github_token.as_deref(),
+    )
+    .await
+    {
+        let conversation_id = data
+            .conversation_id
+            .clone()
+            .unwrap_or_else(ConversationStore::generate_id);
+        //Turns of a conversation are answered one at a time, so that none of them is lost
+        let turn = conversations
+            .get_ref()
+            .begin_turn(&conversation_id)
+            .ok_or_else(|| ErrorConflict("The conversation has a query in progress"))?;
+        let session = match &data.conversation_id {
+            Some(id) => {
+                let session = conversations
+                    .get(id)
+                    .ok_or_else(|| ErrorNotFound("Conversation not found"))?;
+                if session.repository != data.repository {
+                    return Err(ErrorBadRequest(
+                        "Conversation belongs to a different repository",
+                    ));
+                }
+                Some(session)
+            }
+            None => None,
+        };
+        let mut data = data.into_inner();
+        data.github_token = github_token;
+
+        if prefers_json(accept) {
+            let sender = EventSender::collector();
+            let result = handle_query(
+                data,
+                conversation_id,
+                session,
+                db.get_ref(),
+                model.get_ref(),
+                chat.get_ref(),
+                contents.get_ref(),
+                conversations.get_ref(),
+                &sender,
+            )
+            .await;
+            drop(turn);
+            let status = response_status(&result);
+            let error = result.err().map(|e| e.to_string());
+            let response = QueryResponse::from_events(sender.collected(), error);
+            return Ok(Either::Right(HttpResponse::build(status).json(response)));
+        }
+
         let (sender, rx) = sse::channel(SSE_CHANNEL_BUFFER_SIZE);
+        let sender = EventSender::Sse(sender);
 
         actix_rt::spawn(async move {
-            let result = async {
-                let mut conversation = Conversation::initiate(
-                    data.into_inner(),
-                    db.get_ref().clone(),
-                    model.get_ref().clone(),
-                    sender.clone(),
-                )
-                .await?;
-                conversation.generate().await?;
-
-                Ok::<(), anyhow::Error>(())
-            };
-            if let Err(e) = result.await {
+            let _turn = turn;
+            let result = handle_query(
+                data,
+                conversation_id,
+                session,
+                db.get_ref(),
+                model.get_ref(),
+                chat.get_ref(),
+                contents.get_ref(),
+                conversations.get_ref(),
+                &sender,
+            )
+            .await;
+            if let Err(e) = result {
                 eprintln!("/query error: {}", e);
                 emit(&sender, QueryEvent::Error(Some(e.to_string().into()))).await;
             }
         });
 
-        Ok(rx)
+        Ok(Either::Left(rx))
     } else {
         Err(ErrorNotFound("Repository is not indexed"))
     }
 }
 
+#[get("/jobs/{id}")]
+async fn job(
+    request: HttpRequest,
+    id: web::Path<String>,
+    accept: Option<Header<Accept>>,
+    jobs: web::Data<Arc<EmbedJobs>>,
+    access: web::Data<Arc<AccessCache>>,
+) -> Result<impl Responder> {
+    let mut status = jobs
+        .get(&id)
+        .ok_or_else(|| ErrorNotFound("Job not found"))?;
+    //Jobs submitted with a token are hidden from requests without a token that can read the repository
+    let (repository, token_scoped) = {
+        let status = status.borrow();
+        (status.repository.clone(), status.token_scoped)
+    };
+    let can_read = match github_token(&request) {
+        _ if !token_scoped => true,
+        Some(github_token) => access.can_read(&repository, &github_token).await,
+        None => false,
+    };
+    if !can_read {
+        return Err(ErrorNotFound("Job not found"));
+    }
+
+    if prefers_json(accept) {
+        let status = status.borrow().clone();
+        return Ok(Either::Right(HttpResponse::Ok().json(status)));
+    }
+
+    let (sender, rx) = sse::channel(SSE_CHANNEL_BUFFER_SIZE);
+    let sender = EventSender::Sse(sender);
+
+    actix_rt::spawn(async move {
+        loop {
+            let current = status.borrow_and_update().clone();
+            let is_finished = current.is_finished();
+            if emit(&sender, current).await.is_err() || is_finished {
+                break;
+            }
+            if status.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(Either::Left(rx))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_query(
+    data: Query,
+    conversation_id: String,
+    session: Option<ConversationSession>,
+    db: &Arc<DynRepositoryEmbeddingsDB>,
+    model: &Arc<Fastembed>,
+    chat: &Arc<OpenAIBackend>,
+    contents: &ContentStore,
+    conversations: &ConversationStore,
+    sender: &EventSender,
+) -> anyhow::Result<()> {
+    let mut conversation = Conversation::initiate(
+        data,
+        conversation_id.clone(),
+        session,
+        db.clone(),
+        model.clone(),
+        chat.clone(),
+        contents,
+        sender.clone(),
+    )
+    .await?;
+    conversation.generate().await?;
+    conversations.insert(conversation_id, conversation.into_session());
+    Ok(())
+}
+
+//Whether the client asked for a single JSON document instead of an SSE stream
+fn prefers_json(accept: Option<Header<Accept>>) -> bool {
+    accept
+        .map(|accept| accept.preference().essence_str() == "application/json")
+        .unwrap_or_default()
+}
+
+fn response_status(result: &anyhow::Result<()>) -> StatusCode {
+    match result {
+        Ok(()) => StatusCode::OK,
+        Err(e) => error_status(RequestError::kind(e)),
+    }
+}
+
+//Requests that can't be served as asked are bad requests, failures of upstream services are bad gateways
+fn error_status(kind: Option<RequestErrorKind>) -> StatusCode {
+    match kind {
+        Some(RequestErrorKind::BadInput) => StatusCode::BAD_REQUEST,
+        Some(RequestErrorKind::Upstream) => StatusCode::BAD_GATEWAY,
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
 #[get("/collection")]
 async fn repo(
+    request: HttpRequest,
     data: ActixQuery<Repository>,
-    db: web::Data<Arc<QdrantDB>>,
+    db: web::Data<Arc<DynRepositoryEmbeddingsDB>>,
+    access: web::Data<Arc<AccessCache>>,
 ) -> Result<impl Responder> {
-    let is_indexed = db.is_indexed(&data.into_inner()).await.unwrap_or_default();
+    let github_token = github_token(&request);
+    let is_indexed = is_visible(
+        db.get_ref().as_ref(),
+        access.get_ref(),
+        &data.into_inner(),
+        github_token.as_deref(),
+    )
+    .await;
 
     if is_indexed {
         Ok(HttpResponse::Ok())
@@ -113,3 +419,248 @@ async fn repo(
         Err(ErrorNotFound("Repository is not indexed"))
     }
 }
+
+#[delete("/collection")]
+async fn delete_repo(
+    request: HttpRequest,
+    data: ActixQuery<Repository>,
+    db: web::Data<Arc<DynRepositoryEmbeddingsDB>>,
+    contents: web::Data<Arc<ContentStore>>,
+    jobs: web::Data<Arc<EmbedJobs>>,
+) -> Result<impl Responder> {
+    if !is_admin(&request) {
+        return Err(ErrorUnauthorized(
+            "Deleting a collection requires the admin token",
+        ));
+    }
+    let repository = data.into_inner();
+    //Operators can delete token-scoped collections too
+    let is_indexed = db.is_indexed(&repository).await.unwrap_or_default();
+    if !is_indexed {
+        return Err(ErrorNotFound("Repository is not indexed"));
+    }
+    if jobs.is_in_flight(&repository) {
+        return Err(ErrorConflict("The repository is being embedded"));
+    }
+
+    db.delete_repo(&repository)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let contents = contents.get_ref().clone();
+    actix_rt::task::spawn_blocking(move || contents.delete(&repository))
+        .await
+        .map_err(ErrorInternalServerError)?
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok())
+}
+
+#[get("/collections")]
+async fn repos(
+    request: HttpRequest,
+    db: web::Data<Arc<DynRepositoryEmbeddingsDB>>,
+    access: web::Data<Arc<AccessCache>>,
+) -> Result<impl Responder> {
+    let github_token = github_token(&request);
+    let mut repositories: Vec<RepositoryMetadata> = Vec::new();
+    for metadata in db.list_repos().await.map_err(ErrorInternalServerError)? {
+        if can_access(&metadata, access.get_ref(), github_token.as_deref()).await {
+            repositories.push(metadata);
+        }
+    }
+    Ok(HttpResponse::Ok().json(repositories))
+}
+
+//The optional per-request GitHub token
+fn github_token(request: &HttpRequest) -> Option<String> {
+    request
+        .headers()
+        .get(GITHUB_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+}
+
+//Whether the request carries the operators' `ADMIN_TOKEN`, admin routes are disabled if it's unset
+fn is_admin(request: &HttpRequest) -> bool {
+    let admin_token = std::env::var("ADMIN_TOKEN").unwrap_or_default();
+    let token = request
+        .headers()
+        .get(ADMIN_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok());
+    !admin_token.is_empty() && token == Some(admin_token.as_str())
+}
+
+//Whether the repository is indexed and its collection is visible to the request
+async fn is_visible(
+    db: &DynRepositoryEmbeddingsDB,
+    access: &AccessCache,
+    repository: &Repository,
+    github_token: Option<&str>,
+) -> bool {
+    if !db.is_indexed(repository).await.unwrap_or_default() {
+        return false;
+    }
+    match db.get_repo_metadata(repository).await {
+        Ok(Some(metadata)) => can_access(&metadata, access, github_token).await,
+        //Chunks are written before the metadata, so a collection without metadata is still being indexed
+        //or failed to be, and may be private
+        Ok(None) | Err(_) => false,
+    }
+}
+
+//Token-scoped collections are hidden from requests without a token that can read the repository
+async fn can_access(
+    metadata: &RepositoryMetadata,
+    access: &AccessCache,
+    github_token: Option<&str>,
+) -> bool {
+    match github_token {
+        _ if !metadata.private => true,
+        Some(github_token) => access.can_read(&metadata.repository(), github_token).await,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Provider;
+    use actix_web::body::to_bytes;
+    use actix_web::test::TestRequest;
+    use serde_json::Value;
+
+    fn repository() -> Repository {
+        Repository {
+            owner: "open-sauced".to_string(),
+            name: "ai".to_string(),
+            branch: "beta".to_string(),
+            provider: Provider::GitHub,
+        }
+    }
+
+    async fn body(response: HttpResponse) -> Value {
+        serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_embed_response_waits_for_the_job() {
+        let jobs = EmbedJobs::default();
+        let id = jobs
+            .submit(
+                repository(),
+                PathRules::default(),
+                false,
+                |sender| async move {
+                    emit(&sender, EmbedEvent::FetchRepo(None)).await;
+                    emit(
+                        &sender,
+                        EmbedEvent::EmbedRepo(Some(json!({
+                            "files": 2,
+                            "deleted_files": 1,
+                            "commit_sha": "abc",
+                            "skipped_files": {},
+                        }))),
+                    )
+                    .await;
+                    emit(&sender, EmbedEvent::SaveEmbeddings(None)).await;
+                    emit(&sender, EmbedEvent::Done(None)).await;
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        let response = embed_response(&jobs, &id).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body(response).await,
+            json!({
+                "job_id": id,
+                "files": 2,
+                "deleted_files": 1,
+                "commit_sha": "abc",
+                "skipped_files": {},
+                "error": null,
+            })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_embed_response_status_of_failed_jobs() {
+        let jobs = EmbedJobs::default();
+        let id = jobs
+            .submit(repository(), PathRules::default(), false, |_| async {
+                Err(RequestError::upstream(anyhow::anyhow!("Unable to fetch")))
+            })
+            .unwrap();
+        let response = embed_response(&jobs, &id).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(body(response).await["error"], "Unable to fetch");
+
+        let id = jobs
+            .submit(repository(), PathRules::default(), false, |_| async {
+                Err(anyhow::anyhow!("Unable to save"))
+            })
+            .unwrap();
+        let response = embed_response(&jobs, &id).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let missing = embed_response(&jobs, "missing").await.unwrap_err();
+        assert_eq!(
+            missing.as_response_error().status_code(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_is_admin() {
+        std::env::set_var("ADMIN_TOKEN", "secret");
+        let request = |token: &str| {
+            TestRequest::default()
+                .insert_header((ADMIN_TOKEN_HEADER, token))
+                .to_http_request()
+        };
+        assert!(is_admin(&request("secret")));
+        assert!(!is_admin(&request("other")));
+        assert!(!is_admin(&TestRequest::default().to_http_request()));
+
+        std::env::set_var("ADMIN_TOKEN", "");
+        assert!(!is_admin(&request("")));
+    }
+
+    #[actix_rt::test]
+    async fn test_token_scoped_job_is_hidden_without_a_token() {
+        let jobs = Arc::new(EmbedJobs::default());
+        let public_id = jobs
+            .submit(repository(), PathRules::default(), false, |_| async {
+                Ok(())
+            })
+            .unwrap();
+        let other_repository = Repository {
+            name: "private".to_string(),
+            ..repository()
+        };
+        let private_id = jobs
+            .submit(other_repository, PathRules::default(), true, |_| async {
+                Ok(())
+            })
+            .unwrap();
+
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(web::Data::new(jobs))
+                .app_data(web::Data::new(Arc::new(AccessCache::default())))
+                .service(job),
+        )
+        .await;
+        let get = |id: &str| {
+            TestRequest::get()
+                .uri(&format!("/jobs/{id}"))
+                .insert_header(("Accept", "application/json"))
+                .to_request()
+        };
+        let response = actix_web::test::call_service(&app, get(&public_id)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = actix_web::test::call_service(&app, get(&private_id)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
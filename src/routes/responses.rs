@@ -0,0 +1,125 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use super::events::EventRecord;
+use crate::{conversation::Source, jobs::JobStatus, utils::functions::Function};
+
+//JSON bodies returned instead of an SSE stream when the client sends `Accept: application/json`
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ToolCall {
+    pub name: String,
+    pub arguments: Value,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct QueryResponse {
+    pub conversation_id: Option<String>,
+    pub answer: Option<String>,
+    pub sources: Vec<Source>,
+    pub tool_calls: Vec<ToolCall>,
+    pub error: Option<String>,
+}
+
+impl QueryResponse {
+    pub fn from_events(events: Vec<EventRecord>, error: Option<String>) -> Self {
+        let mut response = QueryResponse {
+            error,
+            ..Default::default()
+        };
+        for EventRecord { event, data } in events {
+            let data = data.unwrap_or_default();
+            let function = match event {
+                "PROCESS_QUERY" => {
+                    response.conversation_id = data["conversation_id"].as_str().map(str::to_string);
+                    None
+                }
+                "SEARCH_CODEBASE" => Some(Function::SearchCodebase),
+                "SEARCH_FILE" => Some(Function::SearchFile),
+                "SEARCH_PATH" => Some(Function::SearchPath),
+                "DONE" => {
+                    response.answer = data["answer"].as_str().map(str::to_string);
+                    response.sources =
+                        serde_json::from_value(data["sources"].clone()).unwrap_or_default();
+                    None
+                }
+                _ => None,
+            };
+            if let Some(function) = function {
+                response.tool_calls.push(ToolCall {
+                    name: function.to_string(),
+                    arguments: data,
+                });
+            }
+        }
+        response
+    }
+}
+
+//Summary of a finished `/embed` job
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct EmbedResponse {
+    pub job_id: String,
+    pub files: Option<u64>,
+    pub deleted_files: Option<u64>,
+    pub commit_sha: Option<String>,
+    pub skipped_files: Option<Value>,
+    pub error: Option<String>,
+}
+
+impl From<JobStatus> for EmbedResponse {
+    fn from(status: JobStatus) -> Self {
+        //The progress is the data of the EMBED_REPO event, if the job got that far
+        let progress = status.progress.unwrap_or_default();
+        EmbedResponse {
+            job_id: status.id,
+            files: progress["files"].as_u64(),
+            deleted_files: progress["deleted_files"].as_u64(),
+            commit_sha: progress["commit_sha"].as_str().map(str::to_string),
+            skipped_files: progress.get("skipped_files").cloned(),
+            error: status.error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_query_response_from_events() {
+        let events = vec![
+            EventRecord {
+                event: "PROCESS_QUERY",
+                data: Some(json!({ "conversation_id": "id" })),
+            },
+            EventRecord {
+                event: "SEARCH_CODEBASE",
+                data: Some(json!({ "query": "license" })),
+            },
+            EventRecord {
+                event: "GENERATE_RESPONSE",
+                data: None,
+            },
+            EventRecord {
+                event: "DONE",
+                data: Some(json!({ "answer": "answer", "sources": [] })),
+            },
+        ];
+
+        assert_eq!(
+            QueryResponse::from_events(events, None),
+            QueryResponse {
+                conversation_id: Some("id".to_string()),
+                answer: Some("answer".to_string()),
+                sources: vec![],
+                tool_calls: vec![ToolCall {
+                    name: "search_codebase".to_string(),
+                    arguments: json!({ "query": "license" }),
+                }],
+                error: None,
+            }
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//Why a request failed, so that `/query` and `/embed` respond with a matching status
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestErrorKind {
+    //The request can't be served as asked
+    BadInput,
+    //An upstream service, e.g. the chat completion backend or the repository's provider, failed or responded with something unusable
+    Upstream,
+}
+
+//Wraps the error of a failed request with its kind, its message is the wrapped error's
+#[derive(Debug)]
+pub struct RequestError {
+    pub kind: RequestErrorKind,
+    error: anyhow::Error,
+}
+
+impl RequestError {
+    pub fn bad_input(error: impl Into<anyhow::Error>) -> anyhow::Error {
+        Self::wrap(RequestErrorKind::BadInput, error.into())
+    }
+
+    pub fn upstream(error: impl Into<anyhow::Error>) -> anyhow::Error {
+        Self::wrap(RequestErrorKind::Upstream, error.into())
+    }
+
+    fn wrap(kind: RequestErrorKind, error: anyhow::Error) -> anyhow::Error {
+        anyhow::Error::new(RequestError { kind, error })
+    }
+
+    //None for other errors, e.g. of the vector DB
+    pub fn kind(error: &anyhow::Error) -> Option<RequestErrorKind> {
+        error.downcast_ref::<RequestError>().map(|error| error.kind)
+    }
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for RequestError {}
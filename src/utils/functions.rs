@@ -1,7 +1,10 @@
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
 use crate::{
-    constants::FILE_CHUNKER_CAPACITY_RANGE,
+    chunker::{chunk_file, FileChunk},
+    constants::{HYBRID_SEARCH_CANDIDATE_FILES, RECIPROCAL_RANK_FUSION_K},
+    contents::Snapshot,
     conversation::RelevantChunk,
     db::RepositoryEmbeddingsDB,
     embeddings::{cosine_similarity, Embeddings, EmbeddingsModel},
@@ -20,65 +23,244 @@ functions_enum! {
     (Done, "done"),
 }
 
-pub async fn search_codebase<M: EmbeddingsModel, D: RepositoryEmbeddingsDB>(
+pub async fn search_codebase<M: EmbeddingsModel, D: RepositoryEmbeddingsDB + ?Sized>(
     query: &str,
     repository: &Repository,
     model: &M,
     db: &D,
+    snapshot: Option<&Snapshot>,
     files_limit: usize,
     chunks_limit: usize,
 ) -> Result<Vec<RelevantChunk>> {
     let query_embeddings = model.query_embed(query)?;
-    let relevant_files = db
-        .get_relevant_files(repository, query_embeddings, files_limit)
-        .await?
-        .file_paths;
-    let mut relevant_chunks: Vec<RelevantChunk> = Vec::new();
-    for path in relevant_files {
-        let chunks = search_file(&path, query, repository, model, chunks_limit).await?;
-        relevant_chunks.extend(chunks);
+    //Both searches rank more files than the limit, so that a file ranked high by only one of them can make it
+    let candidate_files = files_limit.max(HYBRID_SEARCH_CANDIDATE_FILES);
+    let vector_chunks = db
+        .get_relevant_chunks(
+            repository,
+            query_embeddings,
+            None,
+            candidate_files,
+            chunks_limit,
+        )
+        .await?;
+    let lexical_chunks = db
+        .get_lexical_chunks(repository, query, candidate_files, chunks_limit)
+        .await?;
+    let mut relevant_chunks = reciprocal_rank_fusion(
+        vec![vector_chunks, lexical_chunks],
+        files_limit,
+        chunks_limit,
+    );
+    if let Some(snapshot) = snapshot {
+        let paths: HashSet<String> = relevant_chunks
+            .iter()
+            .map(|chunk| chunk.path.clone())
+            .collect();
+        let files = read_snapshot_files(snapshot, paths.into_iter().collect()).await?;
+        restore_chunks(&mut relevant_chunks, |path| {
+            files.get(path).cloned().flatten()
+        });
     }
-
     Ok(relevant_chunks)
 }
 
-pub async fn search_file<M: EmbeddingsModel>(
+#[allow(clippy::too_many_arguments)]
+pub async fn search_file<M: EmbeddingsModel, D: RepositoryEmbeddingsDB + ?Sized>(
     path: &str,
     query: &str,
     repository: &Repository,
     model: &M,
+    db: &D,
+    snapshot: Option<&Snapshot>,
     chunks_limit: usize,
+    commit_sha: Option<&str>,
+    github_token: Option<&str>,
 ) -> Result<Vec<RelevantChunk>> {
-    let file_content = fetch_file_content(repository, path)
-        .await
-        .unwrap_or_default();
-
-    let splitter = text_splitter::TextSplitter::default().with_trim_chunks(true);
-
-    let chunks: Vec<&str> = splitter
-        .chunks(&file_content, FILE_CHUNKER_CAPACITY_RANGE)
-        .collect();
+    let query_embeddings = model.query_embed(query)?;
+    let file_content = match snapshot {
+        Some(snapshot) => read_snapshot_files(snapshot, vec![path.to_string()])
+            .await?
+            .remove(path)
+            .flatten(),
+        None => None,
+    };
+    let mut relevant_chunks = db
+        .get_relevant_chunks(
+            repository,
+            query_embeddings.clone(),
+            Some(path),
+            1,
+            chunks_limit,
+        )
+        .await?;
+    if !relevant_chunks.is_empty() {
+        restore_chunks(&mut relevant_chunks, |_| file_content.clone());
+        return Ok(relevant_chunks);
+    }
 
-    let cleaned_chunks: Vec<String> = clean_chunks(chunks);
-    let chunks_embeddings: Vec<Embeddings> = model.embed(cleaned_chunks.clone())?;
+    //The file wasn't embedded, so it's read from the snapshot and embedded instead
+    //Files missing from the snapshot are fetched at the indexed commit
+    let file_content = match file_content {
+        Some(file_content) => file_content,
+        None => fetch_file_content(repository, commit_sha, path, github_token)
+            .await
+            .unwrap_or_default(),
+    };
+    let chunks: Vec<FileChunk> = chunk_file(path, &file_content, model.chunk_sizer());
+    if chunks.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    let query_embeddings = model.query_embed(query)?;
+    let chunks_embeddings: Vec<Embeddings> = model.embed(
+        chunks
+            .iter()
+            .map(|chunk| chunk.content.as_str())
+            .collect::<Vec<&str>>(),
+    )?;
 
     let similarities: Vec<f32> = similarity_score(chunks_embeddings, query_embeddings);
 
     let indices = get_top_n_indices(similarities, chunks_limit);
 
-    let relevant_chunks: Vec<RelevantChunk> = indices
+    let mut relevant_chunks: Vec<RelevantChunk> = indices
         .iter()
-        .map(|index| RelevantChunk {
-            path: path.to_string(),
-            content: cleaned_chunks[*index].to_string(),
+        .map(|index| {
+            let FileChunk {
+                content,
+                start_line,
+                end_line,
+                symbol,
+                headings,
+            } = chunks[*index].clone();
+            RelevantChunk {
+                path: path.to_string(),
+                content,
+                start_line,
+                end_line,
+                symbol,
+                headings,
+            }
         })
         .collect();
+    restore_chunks(&mut relevant_chunks, |_| Some(file_content.clone()));
     Ok(relevant_chunks)
 }
 
-pub async fn search_path<D: RepositoryEmbeddingsDB>(
+//A file of the fused rankings, with the fused scores of its chunks
+struct FusedFile {
+    path: String,
+    score: f32,
+    chunks: Vec<(RelevantChunk, f32)>,
+}
+
+//Fuses rankings of chunks grouped by file, as the DB's searches return them
+//Files, and the chunks of each file, are scored by the sum of `1 / (k + rank)` over the rankings they're in
+//Ties keep the order of the earlier rankings
+fn reciprocal_rank_fusion(
+    rankings: Vec<Vec<RelevantChunk>>,
+    files_limit: usize,
+    chunks_limit: usize,
+) -> Vec<RelevantChunk> {
+    let reciprocal_rank = |rank: usize| 1.0 / (RECIPROCAL_RANK_FUSION_K + rank as f32);
+
+    let mut files: Vec<FusedFile> = Vec::new();
+    for ranking in rankings {
+        let mut file_rank = 0;
+        let mut chunk_rank = 0;
+        let mut file_index = 0;
+        for (index, chunk) in ranking.into_iter().enumerate() {
+            if index == 0 || files[file_index].path != chunk.path {
+                file_rank += 1;
+                chunk_rank = 0;
+                file_index = match files.iter().position(|file| file.path == chunk.path) {
+                    Some(file_index) => file_index,
+                    None => {
+                        files.push(FusedFile {
+                            path: chunk.path.clone(),
+                            score: 0.0,
+                            chunks: Vec::new(),
+                        });
+                        files.len() - 1
+                    }
+                };
+                files[file_index].score += reciprocal_rank(file_rank);
+            }
+            chunk_rank += 1;
+
+            let chunks = &mut files[file_index].chunks;
+            let fused_chunk = chunks.iter_mut().find(|(fused_chunk, _)| {
+                fused_chunk.start_line == chunk.start_line && fused_chunk.end_line == chunk.end_line
+            });
+            match fused_chunk {
+                Some((_, score)) => *score += reciprocal_rank(chunk_rank),
+                None => chunks.push((chunk, reciprocal_rank(chunk_rank))),
+            }
+        }
+    }
+
+    files.sort_by(|a, b| b.score.total_cmp(&a.score));
+    files
+        .into_iter()
+        .take(files_limit)
+        .flat_map(|FusedFile { mut chunks, .. }| {
+            chunks.sort_by(|a, b| b.1.total_cmp(&a.1));
+            chunks
+                .into_iter()
+                .take(chunks_limit)
+                .map(|(chunk, _)| chunk)
+        })
+        .collect()
+}
+
+//Replaces the whitespace-cleaned content of chunks with their lines as formatted in the file
+//Chunks whose lines don't match the content, e.g. parts of a long line, are kept as they are
+//Reads the files off the async executor, files missing from the snapshot are None
+async fn read_snapshot_files(
+    snapshot: &Snapshot,
+    paths: Vec<String>,
+) -> Result<HashMap<String, Option<String>>> {
+    let snapshot = snapshot.clone();
+    let files = actix_rt::task::spawn_blocking(move || {
+        paths
+            .into_iter()
+            .map(|path| {
+                let file_content = snapshot.read_file(&path);
+                (path, file_content)
+            })
+            .collect()
+    })
+    .await?;
+    Ok(files)
+}
+
+fn restore_chunks(
+    relevant_chunks: &mut [RelevantChunk],
+    mut read_file: impl FnMut(&str) -> Option<String>,
+) {
+    let mut files: HashMap<String, Option<String>> = HashMap::new();
+    for chunk in relevant_chunks.iter_mut() {
+        let file_content = files
+            .entry(chunk.path.clone())
+            .or_insert_with(|| read_file(&chunk.path));
+        let Some(file_content) = file_content else {
+            continue;
+        };
+
+        let lines: String = file_content
+            .lines()
+            .skip(chunk.start_line.saturating_sub(1))
+            .take((chunk.end_line + 1).saturating_sub(chunk.start_line.max(1)))
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let cleaned_lines = lines.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if cleaned_lines == chunk.content {
+            chunk.content = lines;
+        }
+    }
+}
+
+pub async fn search_path<D: RepositoryEmbeddingsDB + ?Sized>(
     path: &str,
     repository: &Repository,
     db: &D,
@@ -127,14 +309,6 @@ pub fn relevant_chunks_to_completion_message(
     }
 }
 
-//Remove extra whitespaces from chunks
-fn clean_chunks(chunks: Vec<&str>) -> Vec<String> {
-    chunks
-        .iter()
-        .map(|s| s.split_whitespace().collect::<Vec<&str>>().join(" "))
-        .collect()
-}
-
 //Compute cosine similarity between query and file content chunks
 fn similarity_score(files_embeddings: Vec<Embeddings>, query_embeddings: Embeddings) -> Vec<f32> {
     files_embeddings
@@ -149,3 +323,109 @@ fn get_top_n_indices(similarity_scores: Vec<f32>, n: usize) -> Vec<usize> {
     indexed_vec.par_sort_by(|a, b| b.1.partial_cmp(a.1).unwrap());
     indexed_vec.iter().map(|x| x.0).take(n).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_chunks() {
+        let file_content = "fn main() {\n    run();\n}\n\nfn run() {}\n";
+        let chunk = |content: &str, start_line: usize, end_line: usize| RelevantChunk {
+            path: "src/main.rs".to_string(),
+            content: content.to_string(),
+            start_line,
+            end_line,
+            symbol: None,
+            headings: Vec::new(),
+        };
+        let mut relevant_chunks = vec![
+            chunk("fn main() { run(); }", 1, 3),
+            chunk("fn run() {}", 5, 5),
+            //Doesn't match the lines of the file
+            chunk("fn other() {}", 5, 5),
+        ];
+
+        restore_chunks(&mut relevant_chunks, |_| Some(file_content.to_string()));
+        assert_eq!(relevant_chunks[0].content, "fn main() {\n    run();\n}");
+        assert_eq!(relevant_chunks[1].content, "fn run() {}");
+        assert_eq!(relevant_chunks[2].content, "fn other() {}");
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let chunk = |path: &str, start_line: usize| RelevantChunk {
+            path: path.to_string(),
+            content: format!("{path}:{start_line}"),
+            start_line,
+            end_line: start_line,
+            symbol: None,
+            headings: Vec::new(),
+        };
+        let vector_chunks = vec![
+            chunk("README.md", 1),
+            chunk("src/routes/mod.rs", 10),
+            chunk("src/routes/mod.rs", 1),
+            chunk("src/main.rs", 1),
+            chunk("src/github/mod.rs", 40),
+        ];
+        let lexical_chunks = vec![
+            chunk("src/github/mod.rs", 1),
+            chunk("src/github/mod.rs", 40),
+            chunk("src/routes/mod.rs", 1),
+        ];
+
+        let contents = |chunks: Vec<RelevantChunk>| -> Vec<String> {
+            chunks.into_iter().map(|chunk| chunk.content).collect()
+        };
+        let fused_chunks =
+            reciprocal_rank_fusion(vec![vector_chunks.clone(), lexical_chunks.clone()], 2, 1);
+        assert_eq!(
+            contents(fused_chunks),
+            vec!["src/routes/mod.rs:1", "src/github/mod.rs:40"]
+        );
+        let fused_chunks = reciprocal_rank_fusion(vec![vector_chunks, lexical_chunks], 3, 2);
+        assert_eq!(
+            contents(fused_chunks),
+            vec![
+                "src/routes/mod.rs:1",
+                "src/routes/mod.rs:10",
+                "src/github/mod.rs:40",
+                "src/github/mod.rs:1",
+                "README.md:1",
+            ]
+        );
+        //Without lexical matches, the vector ranking is kept
+        let fused_chunks = reciprocal_rank_fusion(vec![vec![chunk("a.rs", 1)], vec![]], 3, 2);
+        assert_eq!(contents(fused_chunks), vec!["a.rs:1"]);
+    }
+
+    #[test]
+    fn test_relevant_chunks_to_completion_message() {
+        let relevant_chunks = vec![
+            RelevantChunk {
+                path: "README.md".to_string(),
+                content: "## Docker\n\nRun the image.".to_string(),
+                start_line: 8,
+                end_line: 10,
+                symbol: None,
+                headings: vec!["Installation".to_string(), "Docker".to_string()],
+            },
+            RelevantChunk {
+                path: "src/main.rs".to_string(),
+                content: "fn main() {}".to_string(),
+                start_line: 1,
+                end_line: 1,
+                symbol: Some("main".to_string()),
+                headings: Vec::new(),
+            },
+        ];
+
+        let message =
+            relevant_chunks_to_completion_message(Function::SearchCodebase, relevant_chunks);
+        assert_eq!(
+            message.content,
+            "##Relevant file chunk##\nPath argument:README.md\nLines:8-10\nSection:Installation > Docker\nRelevant content: ## Docker\n\nRun the image.\n\n##Relevant file chunk##\nPath argument:src/main.rs\nLines:1-1\nSymbol:main\nRelevant content: fn main() {}"
+        );
+    }
+}
@@ -30,6 +30,16 @@ macro_rules!  sse_events {
             }
         }
 
+        impl From<$name> for EventRecord {
+            fn from(event: $name) -> EventRecord {
+                match event {
+                    $(
+                        $name::$key(data) => EventRecord { event: $value, data }
+                    ),*
+                }
+            }
+        }
+
         impl $name {
 
         }
@@ -57,11 +67,11 @@ macro_rules! functions_enum {
             $($key),*
         }
 
-        impl ToString for $name {
-            fn to_string(&self) -> String {
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 match self {
                     $(
-                        &$name::$key => $value.to_string()
+                        &$name::$key => write!(f, "{}", $value)
                     ),*
                 }
             }
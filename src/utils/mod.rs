@@ -1,2 +1,3 @@
+pub mod errors;
 pub mod functions;
 pub mod macros;